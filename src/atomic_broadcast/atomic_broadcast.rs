@@ -2,12 +2,17 @@ extern crate raft as tikv_raft;
 
 use std::path::PathBuf;
 use std::time::Duration;
-use hocon::HoconLoader;
+use hocon::{Hocon, HoconLoader};
 use leaderpaxos::storage::SequenceTraits;
 
 use super::{
     client::{Client, LocalClientMessage},
-    raft::{RaftComp, ReconfigurationPolicy as RaftReconfigurationPolicy},
+    kv::KVWorkload,
+    linearizability::check_history,
+    messages::group_of,
+    paxos::PaxosComp,
+    raft::{describe_rawraft_config, rawraft_config, RaftComp},
+    replicated_log::ReplicatedLog,
 };
 use crate::{atomic_broadcast::atomic_broadcast_request::*, GetSequence};
 use crate::atomic_broadcast::partitioning_actor::IterationControlMsg;
@@ -15,20 +20,17 @@ use crate::atomic_broadcast::partitioning_actor::IterationControlMsg;
 use hashbrown::HashMap;
 use hdrhistogram::Histogram;
 use kompact::prelude::*;
-use crate::atomic_broadcast::partitioning_actor::PartitioningActor;
+use crate::atomic_broadcast::partitioning_actor::{KVTimestamp, PartitioningActor};
 use std::{str::FromStr, sync::Arc};
 use synchronoise::CountdownEvent;
 
-#[allow(unused_imports)]
-use super::storage::raft::DiskStorage;
-use crate::atomic_broadcast::{raft::RaftCompMsg};
+use super::storage::raft::{DiskStorage, MemStorage};
 
 use kompact::net::buffers::BufferConfig;
 use std::{
     fs::{create_dir_all, OpenOptions},
     io::Write,
 };
-use tikv_raft::storage::MemStorage;
 
 #[derive(Debug)]
 pub enum BenchmarkError {
@@ -213,62 +215,198 @@ impl DistributedBenchmark for AtomicBroadcast {
     }
 }*/
 
-fn get_experiment_configs(last_node_id: u64) -> (Vec<u64>, Vec<u64>) {
-    if last_node_id % 2 == 0 {
-        let initial_config: Vec<_> = (1..last_node_id).collect();
-        let reconfig: Vec<_> = (2..=last_node_id).collect();
-        (initial_config, reconfig)
-    } else {
-        let initial_config: Vec<_> = (1..=last_node_id).collect();
-        (initial_config, vec![])
-    }
-}
-
+// every reconfiguration in the schedule replaces nodes of the previous configuration with new ones
 fn get_reconfig_data(
     s: &str,
     n: u64,
-) -> Result<(u64, Option<(Vec<u64>, Vec<u64>)>), BenchmarkError> {
+    num_reconfigs: usize,
+) -> Result<(u64, Vec<(Vec<u64>, Vec<u64>)>), BenchmarkError> {
+    let mut reconfigurations = Vec::with_capacity(num_reconfigs);
+    let mut config: Vec<u64> = (1..=n).collect();
+    let mut last_node_id = n;
     match s.to_lowercase().as_ref() {
-        "off" => Ok((0, None)),
+        "off" => Ok((0, reconfigurations)),
         "single" => {
-            let reconfig: Vec<u64> = (2..=n + 1).collect();
-            let new_followers: Vec<u64> = vec![];
-            let reconfiguration = Some((reconfig, new_followers));
-            Ok((1, reconfiguration))
+            for _ in 0..num_reconfigs {
+                config.remove(0);
+                last_node_id += 1;
+                config.push(last_node_id);
+                let new_followers: Vec<u64> = vec![];
+                reconfigurations.push((config.clone(), new_followers));
+            }
+            Ok((last_node_id - n, reconfigurations))
         }
         "majority" => {
             let majority = n / 2 + 1;
-            let mut reconfig: Vec<u64> = (1..majority).collect(); // minority i.e. continued node ids
-            for i in 1..=majority {
-                let new_node_id = n + i;
-                reconfig.push(new_node_id);
+            for _ in 0..num_reconfigs {
+                config.truncate((majority - 1) as usize); // minority i.e. continued node ids
+                for _ in 0..majority {
+                    last_node_id += 1;
+                    config.push(last_node_id);
+                }
+                assert_eq!(n, config.len() as u64);
+                let new_followers: Vec<u64> = vec![];
+                reconfigurations.push((config.clone(), new_followers));
             }
-            assert_eq!(n, reconfig.len() as u64);
-            let new_followers: Vec<u64> = vec![];
-            let reconfiguration = Some((reconfig, new_followers));
-            Ok((majority, reconfiguration))
+            Ok((last_node_id - n, reconfigurations))
         }
         _ => Err(BenchmarkError::InvalidMessage(String::from(
             "Got unknown reconfiguration parameter",
         ))),
     }
 }
-type Storage = MemStorage;
+
+// the experiment section of the config file and the raft settings the master needs, parsed once per experiment
+#[derive(Clone, Debug)]
+pub struct ExperimentConfig {
+    pub client_timeout: Duration,
+    pub meta_results_path: Option<String>,
+    pub read_ratio: f64,
+    pub transfer_leader_at: u64,
+    pub discover_leader: bool,
+    pub reconfig_at: Vec<f64>, // fractions of the proposals after which the client proposes the next reconfiguration
+    pub workload: Option<KVWorkload>, // the operations of the clients, None proposes plain log appends
+    pub num_groups: u64, // number of Raft groups per node, the client's keys are partitioned across them
+    pub storage: String,
+    pub durability: String, // how the log is persisted, results are tagged with it to compare the backends and the cost of each mode
+    pub read_only_option: String, // read latencies of ReadIndex and lease-based reads end up in separate files through it
+    pub raft_config: String, // effective raft-rs config of the experiment
+}
+
+impl ExperimentConfig {
+    pub fn load_from_file<P>(path: P) -> ExperimentConfig
+    where
+        P: Into<PathBuf>,
+    {
+        let p: PathBuf = path.into();
+        let config = HoconLoader::new()
+            .load_file(p)
+            .expect("Failed to load file")
+            .hocon()
+            .expect("Failed to load as HOCON");
+        let client_timeout = config["experiment"]["client_timeout"]
+            .as_duration()
+            .expect("Failed to load client timeout");
+        let meta_results_path = config["experiment"]["meta_results_path"].as_string();
+        let read_ratio = config["experiment"]["read_ratio"]
+            .as_f64()
+            .expect("Failed to load read_ratio");
+        let transfer_leader_at = config["experiment"]["transfer_leader_at"]
+            .as_i64()
+            .expect("Failed to load transfer_leader_at") as u64;
+        let discover_leader = config["experiment"]["discover_leader"]
+            .as_bool()
+            .expect("Failed to load discover_leader");
+        let reconfig_at = match &config["experiment"]["reconfig_at"] {
+            Hocon::Array(a) => a
+                .iter()
+                .map(|at| at.as_f64().expect("Failed to load reconfig_at"))
+                .collect(),
+            _ => panic!("Failed to load reconfig_at"),
+        };
+        let workload = config["experiment"]["workload"]
+            .as_string()
+            .expect("Failed to load workload");
+        let workload = match workload.to_lowercase().as_ref() {
+            "log" => None,
+            "kv" => Some(KVWorkload {
+                num_keys: config["experiment"]["kv_num_keys"]
+                    .as_i64()
+                    .expect("Failed to load kv_num_keys") as u64,
+                get_ratio: config["experiment"]["kv_get_ratio"]
+                    .as_f64()
+                    .expect("Failed to load kv_get_ratio"),
+                cas_ratio: config["experiment"]["kv_cas_ratio"]
+                    .as_f64()
+                    .expect("Failed to load kv_cas_ratio"),
+            }),
+            w => panic!("Unknown workload: {}", w),
+        };
+        let num_groups = config["raft"]["num_groups"]
+            .as_i64()
+            .expect("Failed to load num_groups") as u64;
+        let storage = config["raft"]["storage"]
+            .as_string()
+            .expect("Failed to load storage");
+        let durability = if storage == "mem" {
+            storage.clone()
+        } else if config["raft"]["async_log_writer"]
+            .as_bool()
+            .expect("Failed to load async_log_writer")
+        {
+            let group_commit_period = config["raft"]["group_commit_period"]
+                .as_duration()
+                .expect("Failed to load group_commit_period");
+            format!("{}-async-{}us", storage, group_commit_period.as_micros())
+        } else {
+            let durability = config["raft"]["durability"]
+                .as_string()
+                .expect("Failed to load durability");
+            format!("{}-{}", storage, durability)
+        };
+        let read_only_option = config["raft"]["read_only_option"]
+            .as_string()
+            .expect("Failed to load read_only_option");
+        let batch_size = config["raft"]["batch_size"]
+            .as_i64()
+            .expect("Failed to load batch_size");
+        let batch_timeout = config["raft"]["batch_timeout"]
+            .as_duration()
+            .expect("Failed to load batch_timeout");
+        let raft_config = format!(
+            "{},batch_size={},batch_timeout={:?},num_groups={},workload={}",
+            describe_rawraft_config(&rawraft_config(&config, 1, 0)),
+            batch_size,
+            batch_timeout,
+            num_groups,
+            describe_workload(&workload)
+        );
+        ExperimentConfig {
+            client_timeout,
+            meta_results_path,
+            read_ratio,
+            transfer_leader_at,
+            discover_leader,
+            reconfig_at,
+            workload,
+            num_groups,
+            storage,
+            durability,
+            read_only_option,
+            raft_config,
+        }
+    }
+}
+
+fn describe_workload(workload: &Option<KVWorkload>) -> String {
+    match workload {
+        Some(w) => format!(
+            "kv(keys={},get={},cas={})",
+            w.num_keys, w.get_ratio, w.cas_ratio
+        ),
+        None => String::from("log"),
+    }
+}
 
 pub struct AtomicBroadcastMaster {
     num_nodes: Option<u64>,
     num_proposals: Option<u64>,
     concurrent_proposals: Option<u64>,
-    reconfiguration: Option<(Vec<u64>, Vec<u64>)>,
+    reconfigurations: Vec<(u64, (Vec<u64>, Vec<u64>))>, // (number of responses to propose at, configuration)
     system: Option<KompactSystem>,
     finished_latch: Option<Arc<CountdownEvent>>,
     iteration_id: u32,
-    client_comp: Option<Arc<Component<Client>>>,
+    client_comps: Vec<Arc<Component<Client>>>, // one per Raft group
     partitioning_actor: Option<Arc<Component<PartitioningActor>>>,
+    kv_history_f: Option<KFuture<Vec<KVTimestamp>>>, // the clients' histories, collected by the partitioning actor
+    kv_history: Option<Vec<KVTimestamp>>, // history of the latest iteration of a kv workload
     latency_hist: Option<Histogram<u64>>,
+    read_latency_hist: Option<Histogram<u64>>,
     num_timed_out: Vec<u64>,
     experiment_str: Option<String>,
+    raft_config: Option<String>, // effective raft-rs config of the experiment
     meta_results_path: Option<String>,
+    experiment_config: Option<ExperimentConfig>,
 }
 
 impl AtomicBroadcastMaster {
@@ -278,31 +416,45 @@ impl AtomicBroadcastMaster {
             num_nodes: None,
             num_proposals: None,
             concurrent_proposals: None,
-            reconfiguration: None,
+            reconfigurations: vec![],
             system: None,
             finished_latch: None,
             iteration_id: 0,
-            client_comp: None,
+            client_comps: vec![],
             partitioning_actor: None,
+            kv_history_f: None,
+            kv_history: None,
             latency_hist: None,
+            read_latency_hist: None,
             num_timed_out: vec![],
             experiment_str: None,
+            raft_config: None,
             meta_results_path: None,
+            experiment_config: None,
         }
     }
 
-    fn initialise_iteration(
+    // created before the clients, which send it their kv histories
+    fn create_partitioning_actor(
         &self,
         nodes: Vec<ActorPath>,
-        client: ActorPath,
-    ) -> Arc<Component<PartitioningActor>> {
+        prepare_latch: Arc<CountdownEvent>,
+        test_promise: Option<KPromise<Vec<KVTimestamp>>>,
+        num_testers: u32,
+    ) -> (Arc<Component<PartitioningActor>>, ActorPath) {
         let system = self.system.as_ref().unwrap();
-        let prepare_latch = Arc::new(CountdownEvent::new(1));
         /*** Setup partitioning actor ***/
         let (partitioning_actor, unique_reg_f) = system.create_and_register(|| {
-            PartitioningActor::with(prepare_latch.clone(), None, self.iteration_id, nodes, None)
+            PartitioningActor::with(
+                prepare_latch,
+                None,
+                self.iteration_id,
+                nodes,
+                test_promise,
+                num_testers,
+            )
         });
-        unique_reg_f.wait_expect(
+        let partitioning_actor_path = unique_reg_f.wait_expect(
             Duration::from_millis(1000),
             "PartitioningComp failed to register!",
         );
@@ -311,36 +463,65 @@ impl AtomicBroadcastMaster {
         partitioning_actor_f
             .wait_timeout(Duration::from_millis(1000))
             .expect("PartitioningComp never started!");
-        let mut ser_client = Vec::<u8>::new();
-        client
-            .serialise(&mut ser_client)
-            .expect("Failed to serialise ClientComp actorpath");
+        (partitioning_actor, partitioning_actor_path)
+    }
+
+    fn initialise_iteration(
+        &self,
+        partitioning_actor: &Arc<Component<PartitioningActor>>,
+        prepare_latch: Arc<CountdownEvent>,
+        clients: Vec<ActorPath>,
+    ) {
+        // the nodes learn the client of each group, in group order
+        let mut ser_clients = Vec::<u8>::new();
+        for client in clients {
+            client
+                .serialise(&mut ser_clients)
+                .expect("Failed to serialise ClientComp actorpath");
+        }
         partitioning_actor
             .actor_ref()
-            .tell(IterationControlMsg::Prepare(Some(ser_client)));
+            .tell(IterationControlMsg::Prepare(Some(ser_clients)));
         prepare_latch.wait();
-        partitioning_actor
     }
 
     fn create_client(
         &self,
+        group: u64,
+        num_groups: u64,
         nodes_id: HashMap<u64, ActorPath>,
         client_timeout: Duration,
-        reconfig: Option<(Vec<u64>, Vec<u64>)>,
+        read_ratio: f64,
+        workload: Option<KVWorkload>,
+        history_recipient: Option<ActorPath>,
+        transfer_leader_at: u64,
+        discover_leader: bool,
+        reconfigs: Vec<(u64, (Vec<u64>, Vec<u64>))>,
         leader_election_latch: Arc<CountdownEvent>,
     ) -> (Arc<Component<Client>>, ActorPath) {
         let system = self.system.as_ref().unwrap();
         let finished_latch = self.finished_latch.clone().unwrap();
         /*** Setup client ***/
         let initial_config: Vec<_> = (1..=self.num_nodes.unwrap()).map(|x| x as u64).collect();
+        // the group gets every num_groups-th key and its share of the concurrent proposals
+        let num_proposals = (self.num_proposals.unwrap() + num_groups - group - 1) / num_groups;
+        let concurrent_proposals =
+            (self.concurrent_proposals.unwrap() + num_groups - group - 1) / num_groups;
         let (client_comp, unique_reg_f) = system.create_and_register(|| {
             Client::with(
+                group,
+                num_groups,
                 initial_config,
-                self.num_proposals.unwrap(),
-                self.concurrent_proposals.unwrap(),
+                num_proposals,
+                concurrent_proposals,
                 nodes_id,
-                reconfig,
+                reconfigs,
                 client_timeout,
+                read_ratio,
+                workload,
+                history_recipient,
+                transfer_leader_at,
+                discover_leader,
                 leader_election_latch,
                 finished_latch,
             )
@@ -351,7 +532,10 @@ impl AtomicBroadcastMaster {
             .wait_timeout(REGISTER_TIMEOUT)
             .expect("ClientComp never started!");
         let client_path = system
-            .register_by_alias(&client_comp, format!("client{}", &self.iteration_id))
+            .register_by_alias(
+                &client_comp,
+                format!("client{}-{}", &self.iteration_id, group),
+            )
             .wait_expect(REGISTER_TIMEOUT, "Failed to register alias for ClientComp");
         (client_comp, client_path)
     }
@@ -360,6 +544,7 @@ impl AtomicBroadcastMaster {
         &mut self,
         c: &AtomicBroadcastRequest,
         num_clients: u32,
+        config: &ExperimentConfig,
     ) -> Result<(), BenchmarkError> {
         // TODO reconfiguration
         if c.concurrent_proposals > c.number_of_proposals {
@@ -405,7 +590,10 @@ impl AtomicBroadcastMaster {
                 }
                 s if s == "single" || s == "majority" => {
                     let reconfig_policy: &str = &c.reconfig_policy.to_lowercase();
-                    if reconfig_policy != "replace-leader" && reconfig_policy != "replace-follower"
+                    if reconfig_policy != "replace-leader"
+                        && reconfig_policy != "replace-follower"
+                        && reconfig_policy != "promote-learners"
+                        && reconfig_policy != "single-step"
                     {
                         return Err(BenchmarkError::InvalidTest(format!(
                             "Unimplemented Raft transfer policy: {}",
@@ -427,8 +615,52 @@ impl AtomicBroadcastMaster {
                 )));
             }
         };
-        match get_reconfig_data(&c.reconfiguration, c.number_of_nodes) {
-            Ok((additional_n, reconfig)) => {
+        let num_groups = config.num_groups;
+        if num_groups < 1 || c.concurrent_proposals < num_groups {
+            return Err(BenchmarkError::InvalidTest(format!(
+                "Concurrent proposals: {} should be at least the number of Raft groups: {}",
+                c.concurrent_proposals, num_groups
+            )));
+        }
+        if num_groups > 1 && c.algorithm.to_lowercase() == "paxos" {
+            return Err(BenchmarkError::InvalidTest(format!(
+                "Paxos runs a single group, but found: {}",
+                num_groups
+            )));
+        }
+        if let Some(workload) = &config.workload {
+            if config.read_ratio > 0.0 {
+                return Err(BenchmarkError::InvalidTest(format!(
+                    "The kv workload reads through get commands, read_ratio should be 0 but found: {}",
+                    config.read_ratio
+                )));
+            }
+            if workload.num_keys < 1 || workload.get_ratio + workload.cas_ratio > 1.0 {
+                return Err(BenchmarkError::InvalidTest(format!(
+                    "Invalid kv workload: {:?}",
+                    workload
+                )));
+            }
+        }
+        if num_groups > 1
+            && (c.reconfiguration.to_lowercase() != "off" || config.transfer_leader_at > 0)
+        {
+            return Err(BenchmarkError::InvalidTest(format!(
+                "Reconfiguration and leader transfer need a single Raft group, but found: {}",
+                num_groups
+            )));
+        }
+        let reconfig_at = &config.reconfig_at;
+        if reconfig_at.iter().any(|at| *at <= 0.0 || *at >= 1.0)
+            || reconfig_at.windows(2).any(|w| w[0] >= w[1])
+        {
+            return Err(BenchmarkError::InvalidTest(format!(
+                "reconfig_at should be increasing fractions between 0 and 1, but found: {:?}",
+                reconfig_at
+            )));
+        }
+        match get_reconfig_data(&c.reconfiguration, c.number_of_nodes, reconfig_at.len()) {
+            Ok((additional_n, reconfigs)) => {
                 let n = c.number_of_nodes + additional_n;
                 if (num_clients as u64) < n {
                     return Err(BenchmarkError::InvalidTest(format!(
@@ -436,7 +668,11 @@ impl AtomicBroadcastMaster {
                         num_clients, n
                     )));
                 }
-                self.reconfiguration = reconfig;
+                self.reconfigurations = reconfig_at
+                    .iter()
+                    .map(|at| (c.number_of_proposals as f64 * at) as u64)
+                    .zip(reconfigs)
+                    .collect();
                 self.num_nodes = Some(n);
             }
             Err(e) => return Err(e),
@@ -444,50 +680,67 @@ impl AtomicBroadcastMaster {
         Ok(())
     }
 
-    pub fn load_benchmark_config<P>(path: P) -> (Duration, Option<String>)
-    where
-        P: Into<PathBuf>,
-    {
-        let p: PathBuf = path.into();
-        let config = HoconLoader::new()
-            .load_file(p)
-            .expect("Failed to load file")
-            .hocon()
-            .expect("Failed to load as HOCON");
-        let client_timeout = config["experiment"]["client_timeout"]
-            .as_duration()
-            .expect("Failed to load client timeout");
-        let meta_results_path = config["experiment"]["meta_results_path"].as_string();
-        (client_timeout, meta_results_path)
-    }
-
     fn cleanup_iteration(&mut self, last_iteration: bool, exec_time_millis: f64) -> () {
         println!(
             "Cleaning up Atomic Broadcast (master) iteration {}. Exec_time: {}",
             self.iteration_id, exec_time_millis
         );
         let system = self.system.take().unwrap();
-        let client = self.client_comp.take().unwrap();
-        let meta_results = client
-            .actor_ref()
-            .ask_with(|promise| LocalClientMessage::Stop(Ask::new(promise, ())))
-            .wait();
-        self.num_timed_out.push(meta_results.num_timed_out);
+        let clients = std::mem::take(&mut self.client_comps);
+        assert!(!clients.is_empty(), "No client found!");
+        // the nodes only stop once every group's client has stopped them
+        let stop_fs: Vec<_> = clients
+            .iter()
+            .map(|client| {
+                client
+                    .actor_ref()
+                    .ask_with(|promise| LocalClientMessage::Stop(Ask::new(promise, ())))
+            })
+            .collect();
+        let mut num_timed_out = 0;
+        let mut latencies = vec![];
+        let mut read_latencies = vec![];
+        #[cfg(feature = "track_timestamps")]
+        let (mut timestamps, mut leader_changes_t) = (vec![], vec![]);
+        for stop_f in stop_fs {
+            let meta_results = stop_f.wait();
+            num_timed_out += meta_results.num_timed_out;
+            latencies.extend(meta_results.latencies);
+            read_latencies.extend(meta_results.read_latencies);
+            #[cfg(feature = "track_timestamps")]
+            {
+                let (group_timestamps, group_leader_changes_t) = meta_results
+                    .timestamps_leader_changes
+                    .expect("No timestamps results!");
+                timestamps.extend(group_timestamps);
+                leader_changes_t.extend(group_leader_changes_t);
+            }
+        }
+        self.num_timed_out.push(num_timed_out);
         if self.concurrent_proposals == Some(1) || cfg!(feature = "track_latency") {
-            self.persist_latency_results(&meta_results.latencies);
+            self.persist_latency_results(&latencies, false);
+            if !read_latencies.is_empty() {
+                self.persist_latency_results(&read_latencies, true);
+            }
         }
         #[cfg(feature = "track_timestamps")]
         {
-            let (timestamps, leader_changes_t) = meta_results
-                .timestamps_leader_changes
-                .expect("No timestamps results!");
+            timestamps.sort();
+            leader_changes_t.sort_by_key(|(_, t)| *t);
             self.persist_timestamp_results(&timestamps, &leader_changes_t);
         }
 
-        let kill_client_f = system.kill_notify(client);
-        kill_client_f
-            .wait_timeout(REGISTER_TIMEOUT)
-            .expect("Client never died");
+        // the clients sent their histories before replying to the stop
+        if let Some(kv_history_f) = self.kv_history_f.take() {
+            self.kv_history = Some(kv_history_f.wait());
+        }
+
+        for client in clients {
+            let kill_client_f = system.kill_notify(client);
+            kill_client_f
+                .wait_timeout(REGISTER_TIMEOUT)
+                .expect("Client never died");
+        }
 
         if let Some(partitioning_actor) = self.partitioning_actor.take() {
             let kill_pactor_f = system.kill_notify(partitioning_actor);
@@ -499,14 +752,19 @@ impl AtomicBroadcastMaster {
         if last_iteration {
             println!("Cleaning up last iteration");
             self.persist_timeouts_summary();
+            self.persist_raft_config();
             if self.concurrent_proposals == Some(1) || cfg!(feature = "track_latency") {
-                self.persist_latency_summary();
+                self.persist_latency_summary(false);
+                if self.read_latency_hist.as_ref().map_or(false, |h| h.len() > 0) {
+                    self.persist_latency_summary(true);
+                }
             }
             self.num_nodes = None;
-            self.reconfiguration = None;
+            self.reconfigurations.clear();
             self.concurrent_proposals = None;
             self.num_proposals = None;
             self.experiment_str = None;
+            self.raft_config = None;
             self.num_timed_out.clear();
             self.iteration_id = 0;
             system
@@ -555,7 +813,8 @@ impl AtomicBroadcastMaster {
             .expect("Failed to flush raw timestamps file");
     }
 
-    fn persist_latency_results(&mut self, latencies: &[Duration]) {
+    // reads are kept apart from proposals, their files are prefixed with read_
+    fn persist_latency_results(&mut self, latencies: &[Duration], reads: bool) {
         let meta_path = self
             .meta_results_path
             .as_ref()
@@ -567,13 +826,18 @@ impl AtomicBroadcastMaster {
             .create(true)
             .append(true)
             .open(format!(
-                "{}raw_{}.data",
+                "{}{}raw_{}.data",
                 &latency_dir,
+                if reads { "read_" } else { "" },
                 self.experiment_str.as_ref().unwrap()
             ))
             .expect("Failed to open latency file");
 
-        let histo = self.latency_hist.as_mut().unwrap();
+        let histo = if reads {
+            self.read_latency_hist.as_mut().unwrap()
+        } else {
+            self.latency_hist.as_mut().unwrap()
+        };
         for l in latencies {
             let latency = l.as_nanos() as u64;
             writeln!(latency_file, "{}", latency).expect("Failed to write raw latency");
@@ -584,7 +848,7 @@ impl AtomicBroadcastMaster {
             .expect("Failed to flush raw latency file");
     }
 
-    fn persist_latency_summary(&mut self) {
+    fn persist_latency_summary(&mut self, reads: bool) {
         let meta_path = self
             .meta_results_path
             .as_ref()
@@ -596,12 +860,17 @@ impl AtomicBroadcastMaster {
             .create(true)
             .append(true)
             .open(format!(
-                "{}summary_{}.out",
+                "{}{}summary_{}.out",
                 &dir,
+                if reads { "read_" } else { "" },
                 self.experiment_str.as_ref().unwrap()
             ))
             .expect("Failed to open latency file");
-        let hist = std::mem::take(&mut self.latency_hist).unwrap();
+        let hist = if reads {
+            std::mem::take(&mut self.read_latency_hist).unwrap()
+        } else {
+            std::mem::take(&mut self.latency_hist).unwrap()
+        };
         let quantiles = [
             0.001, 0.01, 0.005, 0.1, 0.2, 0.3, 0.4, 0.5, 0.6, 0.7, 0.8, 0.9, 0.95, 0.99, 0.999,
         ];
@@ -628,6 +897,28 @@ impl AtomicBroadcastMaster {
         file.flush().expect("Failed to flush histogram file");
     }
 
+    fn persist_raft_config(&mut self) {
+        if let Some(raft_config) = self.raft_config.as_ref() {
+            let meta_path = self
+                .meta_results_path
+                .as_ref()
+                .expect("No meta results path!");
+            let config_file_path = format!("{}/raft_config.out", meta_path);
+            create_dir_all(meta_path).unwrap_or_else(|_| {
+                panic!("Failed to create given directory: {}", config_file_path)
+            });
+            let mut config_file = OpenOptions::new()
+                .create(true)
+                .append(true)
+                .open(config_file_path)
+                .expect("Failed to open raft config file");
+            writeln!(config_file, "{}", self.experiment_str.as_ref().unwrap())
+                .expect("Failed to write raft config file");
+            writeln!(config_file, "{}", raft_config).expect("Failed to write raft config file");
+            config_file.flush().expect("Failed to flush raft config file");
+        }
+    }
+
     fn persist_timeouts_summary(&mut self) {
         let sum: u64 = self.num_timed_out.iter().sum();
         if sum > 0 {
@@ -667,26 +958,37 @@ impl AtomicBroadcastMaster {
         &mut self,
         c: AtomicBroadcastRequest,
         m: &DeploymentMetaData,
+        config: ExperimentConfig,
     ) -> Result<ClientParams, BenchmarkError> {
         println!("Setting up Atomic Broadcast (Master)");
-        self.validate_experiment_params(&c, m.number_of_clients())?;
+        self.validate_experiment_params(&c, m.number_of_clients(), &config)?;
         let experiment_str = format!(
-            "{},{},{},{},{},{}",
+            "{},{},{},{},{},{},{},{},{}",
             c.algorithm,
             c.number_of_nodes,
             c.concurrent_proposals,
             c.number_of_proposals,
             c.reconfiguration,
-            c.reconfig_policy
+            c.reconfig_policy,
+            config.durability,
+            config.read_ratio,
+            config.read_only_option
         );
         self.experiment_str = Some(experiment_str);
+        if c.algorithm.to_lowercase() == "raft" {
+            self.raft_config = Some(config.raft_config.clone());
+        }
+        self.meta_results_path = config.meta_results_path.clone();
+        self.experiment_config = Some(config);
         self.num_proposals = Some(c.number_of_proposals);
         self.concurrent_proposals = Some(c.concurrent_proposals);
         if c.concurrent_proposals == 1
-            || (self.reconfiguration.is_some() && cfg!(feature = "track_latency"))
+            || (!self.reconfigurations.is_empty() && cfg!(feature = "track_latency"))
         {
             self.latency_hist =
                 Some(Histogram::<u64>::new(4).expect("Failed to create latency histogram"));
+            self.read_latency_hist =
+                Some(Histogram::<u64>::new(4).expect("Failed to create read latency histogram"));
         }
         let mut conf = KompactConfig::default();
         conf.load_config_file(CONFIG_PATH);
@@ -696,11 +998,7 @@ impl AtomicBroadcastMaster {
         let system = atomic_broadcast::kompact_system_provider::global()
             .new_remote_system_with_threads_config("atomicbroadcast", 1, conf, bc, tcp_no_delay);
         self.system = Some(system);
-        let last_node_id = if self.reconfiguration.is_some() {
-            c.number_of_nodes + 1
-        } else {
-            c.number_of_nodes
-        };
+        let last_node_id = self.num_nodes.expect("No cached num_nodes");
         let params = ClientParams::with(c.algorithm, last_node_id, c.reconfig_policy);
         Ok(params)
     }
@@ -711,7 +1009,12 @@ impl AtomicBroadcastMaster {
         if self.system.is_none() {
             panic!("No KompactSystem found!")
         }
-        let finished_latch = Arc::new(CountdownEvent::new(1));
+        let config = self
+            .experiment_config
+            .clone()
+            .expect("No cached experiment config");
+        let num_groups = config.num_groups;
+        let finished_latch = Arc::new(CountdownEvent::new(num_groups as usize));
         self.finished_latch = Some(finished_latch);
         self.iteration_id += 1;
         let mut nodes_id: HashMap<u64, ActorPath> = HashMap::new();
@@ -721,35 +1024,70 @@ impl AtomicBroadcastMaster {
         for (id, ap) in nodes.iter().enumerate() {
             nodes_id.insert(id as u64 + 1, ap.clone());
         }
-        let (client_timeout, meta_path) = Self::load_benchmark_config(CONFIG_PATH);
-        self.meta_results_path = meta_path;
-        let leader_election_latch = Arc::new(CountdownEvent::new(1));
-        let (client_comp, client_path) = self.create_client(
-            nodes_id,
-            client_timeout,
-            self.reconfiguration.clone(),
-            leader_election_latch.clone(),
+        let leader_election_latch = Arc::new(CountdownEvent::new(num_groups as usize));
+        let workload = config.workload;
+        // the histories of a kv workload are checked for linearizability
+        let test_promise = match workload {
+            Some(_) => {
+                let (kprom, kfuture) = promise::<Vec<KVTimestamp>>();
+                self.kv_history_f = Some(kfuture);
+                Some(kprom)
+            }
+            None => None,
+        };
+        let prepare_latch = Arc::new(CountdownEvent::new(1));
+        let (partitioning_actor, partitioning_actor_path) = self.create_partitioning_actor(
+            nodes,
+            prepare_latch.clone(),
+            test_promise,
+            num_groups as u32,
         );
-        let partitioning_actor = self.initialise_iteration(nodes, client_path);
+        let history_recipient = workload.as_ref().map(|_| partitioning_actor_path);
+        let mut client_paths = Vec::with_capacity(num_groups as usize);
+        for group in 0..num_groups {
+            // only the first group is reconfigured
+            let reconfigs = if group == 0 {
+                self.reconfigurations.clone()
+            } else {
+                vec![]
+            };
+            let (client_comp, client_path) = self.create_client(
+                group,
+                num_groups,
+                nodes_id.clone(),
+                config.client_timeout,
+                config.read_ratio,
+                workload.clone(),
+                history_recipient.clone(),
+                config.transfer_leader_at,
+                config.discover_leader,
+                reconfigs,
+                leader_election_latch.clone(),
+            );
+            self.client_comps.push(client_comp);
+            client_paths.push(client_path);
+        }
+        self.initialise_iteration(&partitioning_actor, prepare_latch, client_paths);
         partitioning_actor
             .actor_ref()
             .tell(IterationControlMsg::Run);
-        leader_election_latch.wait(); // wait until leader is established
-        println!("FIRST LEADER ELECTED");
+        if !config.discover_leader {
+            leader_election_latch.wait(); // wait until every group has a leader
+            println!("FIRST LEADER ELECTED");
+        }
         self.partitioning_actor = Some(partitioning_actor);
-        self.client_comp = Some(client_comp);
     }
 
     fn run_iteration(&mut self) -> () {
         println!("Running Atomic Broadcast experiment!");
-        match self.client_comp {
-            Some(ref client_comp) => {
-                client_comp.actor_ref().tell(LocalClientMessage::Run);
-                let finished_latch = self.finished_latch.take().unwrap();
-                finished_latch.wait();
-            }
-            _ => panic!("No client found!"),
+        if self.client_comps.is_empty() {
+            panic!("No client found!");
         }
+        for client_comp in &self.client_comps {
+            client_comp.actor_ref().tell(LocalClientMessage::Run);
+        }
+        let finished_latch = self.finished_latch.take().unwrap();
+        finished_latch.wait();
     }
 }
 /*
@@ -902,21 +1240,24 @@ impl DistributedBenchmarkMaster for AtomicBroadcastMaster {
     }
 } */
 
-pub struct AtomicBroadcastClient {
+pub struct AtomicBroadcastClient<R: ReplicatedLog> {
     system: Option<KompactSystem>,
-    raft_comp: Option<Arc<Component<RaftComp<Storage>>>>,
+    replica: Option<Arc<Component<R>>>,
 }
 
-impl AtomicBroadcastClient {
-    fn new() -> AtomicBroadcastClient {
+impl<R: ReplicatedLog> AtomicBroadcastClient<R> {
+    fn new() -> AtomicBroadcastClient<R> {
         AtomicBroadcastClient {
             system: None,
-            raft_comp: None,
+            replica: None,
         }
     }
 }
 /*
-impl DistributedBenchmarkClient for AtomicBroadcastClient {
+impl<S> DistributedBenchmarkClient for AtomicBroadcastClient<S>
+where
+    S: RaftStorage + Send + Clone + 'static,
+{
     type ClientConf = ClientParams;
     type ClientData = ActorPath;
 
@@ -963,11 +1304,12 @@ impl DistributedBenchmarkClient for AtomicBroadcastClient {
                     "none" => None,
                     "replace-leader" => Some(RaftReconfigurationPolicy::ReplaceLeader),
                     "replace-follower" => Some(RaftReconfigurationPolicy::ReplaceFollower),
+                    "promote-learners" => Some(RaftReconfigurationPolicy::PromoteLearners),
                     unknown => panic!("Got unknown Raft transfer policy: {}", unknown),
                 };
                 /*** Setup RaftComp ***/
                 let (raft_comp, unique_reg_f) = system.create_and_register(|| {
-                    RaftComp::<Storage>::with(
+                    RaftComp::<S>::with(
                         voters,
                         reconfig_policy.unwrap_or(RaftReconfigurationPolicy::ReplaceFollower),
                     )
@@ -1034,24 +1376,57 @@ impl DistributedBenchmarkClient for AtomicBroadcastClient {
 use super::*;
 use crate::{atomic_broadcast::{self}};
 
+fn create_replica<R: ReplicatedLog>(
+    system: &KompactSystem,
+    voters: Vec<u64>,
+    reconfig_policy: &str,
+) -> (ActorPath, Recipient<GetSequence>, Recipient<Ask<(), Done>>) {
+    let (replica, unique_reg_f) = system.create_and_register(|| R::create(voters, reconfig_policy));
+    unique_reg_f.wait_expect(REGISTER_TIMEOUT, "Replica failed to register!");
+    let self_path = system
+        .register_by_alias(&replica, R::PATH)
+        .wait_expect(REGISTER_TIMEOUT, "Replica failed to register alias!");
+    let replica_f = system.start_notify(&replica);
+    replica_f
+        .wait_timeout(REGISTER_TIMEOUT)
+        .expect("Replica never started!");
+
+    let sequence: Recipient<GetSequence> = replica
+        .actor_ref()
+        .recipient_with(|gs: GetSequence| R::get_sequence(gs.0));
+    let kill: Recipient<Ask<(), Done>> = replica.actor_ref().recipient_with(R::kill);
+    (self_path, sequence, kill)
+}
+
 fn create_nodes(
     n: u64,
     algorithm: &str,
     reconfig_policy: &str,
-    last_node_id: u64,
+    initial_voters: u64,
+    storage: &str,
 ) -> (
     Vec<KompactSystem>,
     Vec<ActorPath>,
     Vec<Recipient<GetSequence>>,
+    Vec<Recipient<Ask<(), Done>>>,
 ) {
     let mut systems = Vec::with_capacity(n as usize);
     let mut actor_paths = Vec::with_capacity(n as usize);
     let mut actor_refs = Vec::with_capacity(n as usize);
+    let mut kill_refs = Vec::with_capacity(n as usize);
     let mut conf = KompactConfig::default();
     conf.load_config_file(CONFIG_PATH);
     let bc = BufferConfig::from_config_file(CONFIG_PATH);
     bc.validate();
     let tcp_no_delay = true;
+    let create = match (algorithm, storage) {
+        ("paxos", _) => create_replica::<PaxosComp>,
+        ("raft", "mem") => create_replica::<RaftComp<MemStorage>>,
+        ("raft", "disk") => create_replica::<RaftComp<DiskStorage>>,
+        ("raft", unknown) => panic!("Got unknown storage: {}", unknown),
+        (unknown, _) => panic!("Got unknown algorithm: {}", unknown),
+    };
+    let voters: Vec<u64> = (1..=initial_voters).collect();
     for i in 1..=n {
         let system = atomic_broadcast::kompact_system_provider::global()
             .new_remote_system_with_threads_config(
@@ -1061,41 +1436,13 @@ fn create_nodes(
                 bc.clone(),
                 tcp_no_delay,
             );
-        let (actor_path, actor_ref) = match algorithm {
-            "raft" => {
-                let voters = get_experiment_configs(last_node_id).0;
-                let reconfig_policy = match reconfig_policy {
-                    "none" => None,
-                    "replace-leader" => Some(RaftReconfigurationPolicy::ReplaceLeader),
-                    "replace-follower" => Some(RaftReconfigurationPolicy::ReplaceFollower),
-                    unknown => panic!("Got unknown Raft transfer policy: {}", unknown),
-                };
-                /*** Setup RaftComp ***/
-                let (raft_comp, unique_reg_f) = system.create_and_register(|| {
-                    RaftComp::<Storage>::with(
-                        voters,
-                        reconfig_policy.unwrap_or(RaftReconfigurationPolicy::ReplaceFollower),
-                    )
-                });
-                unique_reg_f.wait_expect(REGISTER_TIMEOUT, "RaftComp failed to register!");
-                let self_path = system
-                    .register_by_alias(&raft_comp, RAFT_PATH)
-                    .wait_expect(REGISTER_TIMEOUT, "Communicator failed to register!");
-                let raft_comp_f = system.start_notify(&raft_comp);
-                raft_comp_f
-                    .wait_timeout(REGISTER_TIMEOUT)
-                    .expect("RaftComp never started!");
-
-                let r: Recipient<GetSequence> = raft_comp.actor_ref().recipient();
-                (self_path, r)
-            }
-            unknown => panic!("Got unknown algorithm: {}", unknown),
-        };
+        let (actor_path, actor_ref, kill_ref) = create(&system, voters.clone(), reconfig_policy);
         systems.push(system);
         actor_paths.push(actor_path);
         actor_refs.push(actor_ref);
+        kill_refs.push(kill_ref);
     }
-    (systems, actor_paths, actor_refs)
+    (systems, actor_paths, actor_refs, kill_refs)
 }
 
 fn check_quorum(sequence_responses: &[SequenceResp], quorum_size: usize, num_proposals: u64) {
@@ -1136,6 +1483,16 @@ fn check_uniform_agreement(sequence_responses: &[SequenceResp]) {
     }
 }
 
+fn check_linearizability(history: &[KVTimestamp]) {
+    let result = check_history(history);
+    assert!(
+        result.is_ok(),
+        "History is not linearizable! key: {:?}, num_timestamps: {}",
+        result.err(),
+        history.len()
+    );
+}
+
 pub fn run_experiment(
     algorithm: &str,
     num_nodes: u64,
@@ -1143,7 +1500,8 @@ pub fn run_experiment(
     concurrent_proposals: u64,
     reconfiguration: &str,
     reconfig_policy: &str,
-) {
+    config: ExperimentConfig,
+) -> Vec<SequenceResp> {
     println!("SETUP");
     let mut master = AtomicBroadcastMaster::new();
     let mut experiment = AtomicBroadcastRequest::new();
@@ -1154,23 +1512,25 @@ pub fn run_experiment(
     experiment.reconfiguration = String::from(reconfiguration);
     experiment.reconfig_policy = String::from(reconfig_policy);
     println!("NUM NODES");
-    let num_nodes_needed = match reconfiguration {
-        "off" => num_nodes,
-        "single" => num_nodes + 1,
-        _ => unimplemented!(),
-    };
+    let num_reconfigs = config.reconfig_at.len();
+    let (additional_n, _) = get_reconfig_data(reconfiguration, num_nodes, num_reconfigs)
+        .expect("Failed to get reconfiguration data");
+    let num_nodes_needed = num_nodes + additional_n;
     println!("DEPLOYMENT META DATA");
     let d = DeploymentMetaData::new(num_nodes_needed as u32);
     println!("CREATE NODES");
-    let (client_systems, clients, client_refs) = create_nodes(
+    let (client_systems, clients, client_refs, kill_refs) = create_nodes(
         num_nodes_needed,
         experiment.get_algorithm(),
         experiment.get_reconfig_policy(),
-        num_nodes_needed,
+        num_nodes,
+        &config.storage,
     );
+    let num_groups = config.num_groups;
+    let kv_workload = config.workload.is_some();
     println!("MASTER SETUP");
     master
-        .setup(experiment, &d)
+        .setup(experiment, &d, config)
         .expect("Failed to setup master");
     master.prepare_iteration(clients);
     println!("RUN ITERATION");
@@ -1190,23 +1550,52 @@ pub fn run_experiment(
     let quorum_size = num_nodes as usize / 2 + 1;
     check_quorum(&sequence_responses, quorum_size, num_proposals);
     check_validity(&sequence_responses, num_proposals);
-    check_uniform_agreement(&sequence_responses);
+    for group in 0..num_groups {
+        // the groups order their keys independently
+        let group_responses: Vec<_> = sequence_responses
+            .iter()
+            .map(|sr| {
+                let sequence = sr
+                    .sequence
+                    .iter()
+                    .copied()
+                    .filter(|id| group_of(*id, num_groups) == group)
+                    .collect();
+                SequenceResp::with(sr.node_id, sequence)
+            })
+            .collect();
+        check_uniform_agreement(&group_responses);
+    }
 
     println!("CLEANUP");
     master.cleanup_iteration(true, 0.0);
+    // the clients hand over their kv histories when they are stopped
+    match master.kv_history.take() {
+        Some(history) => check_linearizability(&history),
+        None => assert!(!kv_workload, "No kv history was collected"),
+    }
+    let kill_futures: Vec<_> = kill_refs
+        .iter()
+        .map(|kill_ref| {
+            let (kprom, kfuture) = promise::<Done>();
+            kill_ref.tell(Ask::new(kprom, ()));
+            kfuture
+        })
+        .collect();
+    FutureCollection::collect_results::<Vec<_>>(kill_futures);
     for system in client_systems {
         system.shutdown().expect("Failed to shutdown system");
     }
+    sequence_responses
 }
-/*
 #[test]
-#[ignore]
 fn paxos_normal_test() {
     let num_nodes = 3;
     let num_proposals = 1000;
     let concurrent_proposals = 200;
     let reconfiguration = "off";
     let reconfig_policy = "none";
+    let config = ExperimentConfig::load_from_file(CONFIG_PATH);
     run_experiment(
         "paxos",
         num_nodes,
@@ -1214,17 +1603,18 @@ fn paxos_normal_test() {
         concurrent_proposals,
         reconfiguration,
         reconfig_policy,
+        config,
     );
 }
 
 #[test]
-#[ignore]
 fn paxos_reconfig_test() {
     let num_nodes = 3;
     let num_proposals = 1000;
     let concurrent_proposals = 200;
     let reconfiguration = "single";
     let reconfig_policy = "pull";
+    let config = ExperimentConfig::load_from_file(CONFIG_PATH);
     run_experiment(
         "paxos",
         num_nodes,
@@ -1232,9 +1622,30 @@ fn paxos_reconfig_test() {
         concurrent_proposals,
         reconfiguration,
         reconfig_policy,
+        config,
     );
 }
 
+#[test]
+fn paxos_reconfig_eager_test() {
+    let num_nodes = 3;
+    let num_proposals = 1000;
+    let concurrent_proposals = 200;
+    let reconfiguration = "majority";
+    let reconfig_policy = "eager";
+    let config = ExperimentConfig::load_from_file(CONFIG_PATH);
+    run_experiment(
+        "paxos",
+        num_nodes,
+        num_proposals,
+        concurrent_proposals,
+        reconfiguration,
+        reconfig_policy,
+        config,
+    );
+}
+
+/*
 #[test]
 fn raft_normal_test() {
     println!("running test");
@@ -1284,3 +1695,86 @@ fn raft_reconfig_leader_test() {
         "replace-leader",
     );
 }*/
+
+#[test]
+fn raft_single_step_reconfig_test() {
+    let num_nodes = 3;
+    let num_proposals = 200;
+    let concurrent_proposals = 20;
+    let config = ExperimentConfig::load_from_file(CONFIG_PATH);
+    // adds node 4 and then removes node 1, the client only finishes once both are applied
+    let sequence_responses = run_experiment(
+        "raft",
+        num_nodes,
+        num_proposals,
+        concurrent_proposals,
+        "single",
+        "single-step",
+        config,
+    );
+    let unique_decided = |pid: u64| {
+        let sr = sequence_responses
+            .iter()
+            .find(|sr| sr.node_id == pid)
+            .expect("No sequence of node");
+        sr.sequence.iter().collect::<hashbrown::HashSet<_>>().len()
+    };
+    assert_eq!(
+        unique_decided(4),
+        num_proposals as usize,
+        "Added node did not decide every proposal"
+    );
+    assert!(
+        unique_decided(1) < num_proposals as usize,
+        "Removed node kept deciding proposals"
+    );
+}
+
+#[test]
+fn raft_kv_test() {
+    let num_nodes = 3;
+    let num_proposals = 200;
+    let concurrent_proposals = 20;
+    let mut config = ExperimentConfig::load_from_file(CONFIG_PATH);
+    config.storage = String::from("mem");
+    config.read_ratio = 0.0;
+    config.workload = Some(KVWorkload {
+        num_keys: 5,
+        get_ratio: 0.3,
+        cas_ratio: 0.3,
+    });
+    // checks the clients' kv history for linearizability
+    run_experiment(
+        "raft",
+        num_nodes,
+        num_proposals,
+        concurrent_proposals,
+        "off",
+        "none",
+        config,
+    );
+}
+
+#[test]
+fn paxos_kv_test() {
+    let num_nodes = 3;
+    let num_proposals = 200;
+    let concurrent_proposals = 20;
+    let mut config = ExperimentConfig::load_from_file(CONFIG_PATH);
+    config.read_ratio = 0.0;
+    config.workload = Some(KVWorkload {
+        num_keys: 5,
+        get_ratio: 0.3,
+        cas_ratio: 0.3,
+    });
+    // checks the clients' kv history for linearizability
+    run_experiment(
+        "paxos",
+        num_nodes,
+        num_proposals,
+        concurrent_proposals,
+        "off",
+        "none",
+        config,
+    );
+}
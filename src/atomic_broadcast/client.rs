@@ -1,17 +1,23 @@
+use super::kv::{KVCommand, KVResponse, KVWorkload};
 use super::messages::{
-    AtomicBroadcastDeser, AtomicBroadcastMsg, Proposal, StopMsg as NetStopMsg, StopMsgDeser,
-    RECONFIG_ID,
+    AtomicBroadcastDeser, AtomicBroadcastMsg, Proposal, ProposalRejected, RejectReason,
+    StopMsg as NetStopMsg, StopMsgDeser, RECONFIG_ID,
 };
-use hashbrown::HashMap;
+use super::partitioning_actor::{KVOperation, KVTimestamp, PartitioningActorMsg};
+use hashbrown::{HashMap, HashSet};
 use kompact::prelude::*;
 #[cfg(feature = "track_timestamps")]
 use quanta::{Clock, Instant};
+use rand::Rng;
 use std::{
+    collections::VecDeque,
     sync::Arc,
-    time::{Duration, SystemTime},
+    time::{Duration, SystemTime, UNIX_EPOCH},
 };
 use synchronoise::{event::CountdownError, CountdownEvent};
 
+const REJECTED_RETRY_DELAY: Duration = Duration::from_millis(10); // backoff when the rejection has no leader hint
+
 #[derive(Debug, PartialEq)]
 enum ExperimentState {
     LeaderElection,
@@ -27,8 +33,8 @@ pub enum LocalClientMessage {
 }
 
 enum Response {
-    Normal(u64),
-    Reconfiguration(Vec<u64>),
+    Normal(u64, KVResponse),
+    Reconfiguration(Vec<u64>, Vec<u64>), // (voters, learners)
 }
 
 #[derive(Debug)]
@@ -51,6 +57,7 @@ impl ProposalMetaData {
 pub struct MetaResults {
     pub num_timed_out: u64,
     pub latencies: Vec<Duration>,
+    pub read_latencies: Vec<Duration>,
     pub timestamps_leader_changes: Option<(Vec<Duration>, Vec<(u64, Duration)>)>,
 }
 
@@ -58,11 +65,13 @@ impl MetaResults {
     pub fn with(
         num_timed_out: u64,
         latencies: Vec<Duration>,
+        read_latencies: Vec<Duration>,
         timestamps_leader_changes: Option<(Vec<Duration>, Vec<(u64, Duration)>)>,
     ) -> Self {
         MetaResults {
             num_timed_out,
             latencies,
+            read_latencies,
             timestamps_leader_changes,
         }
     }
@@ -71,14 +80,28 @@ impl MetaResults {
 #[derive(ComponentDefinition)]
 pub struct Client {
     ctx: ComponentContext<Self>,
+    group: u64,      // the Raft group this client proposes to
+    num_groups: u64, // the keys are partitioned across the groups
     num_proposals: u64,
     num_concurrent_proposals: u64,
+    read_ratio: f64,                      // fraction of the operations that are reads
+    reads: HashSet<u64>,                  // ids of the operations that are reads
+    workload: Option<KVWorkload>,         // None proposes plain log appends
+    commands: HashMap<u64, KVCommand>,    // commands of the pending kv operations
+    kv_values: HashMap<u64, u32>, // latest value seen per key, the expected value of the next cas
+    history_recipient: Option<ActorPath>, // gets the kv history as TestDone when the client stops
+    history: Vec<KVTimestamp>,
+    transfer_leader_at: u64, // number of responses after which leadership is transferred, 0 = never
+    pending_transfer: Option<(u64, SystemTime)>, // (target, start time) of an ongoing leader transfer
+    learners_joined: Option<SystemTime>, // when the new nodes of the pending reconfiguration joined as learners
     nodes: HashMap<u64, ActorPath>,
-    reconfig: Option<(Vec<u64>, Vec<u64>)>,
+    reconfigs: VecDeque<(u64, (Vec<u64>, Vec<u64>))>, // (number of responses to propose at, configuration), the first is the next or ongoing one
+    discover_leader: bool, // find the leader through redirects instead of waiting for FirstLeader
     leader_election_latch: Arc<CountdownEvent>,
     finished_latch: Arc<CountdownEvent>,
     latest_proposal_id: u64,
     responses: HashMap<u64, Option<Duration>>,
+    lowest_pending: u64, // the lowest id without a response, sent as the watermark of the commands
     pending_proposals: HashMap<u64, ProposalMetaData>,
     timeout: Duration,
     current_leader: u64,
@@ -105,26 +128,47 @@ pub struct Client {
 
 impl Client {
     pub fn with(
+        group: u64,
+        num_groups: u64,
         initial_config: Vec<u64>,
         num_proposals: u64,
         num_concurrent_proposals: u64,
         nodes: HashMap<u64, ActorPath>,
-        reconfig: Option<(Vec<u64>, Vec<u64>)>,
+        reconfigs: Vec<(u64, (Vec<u64>, Vec<u64>))>,
         timeout: Duration,
+        read_ratio: f64,
+        workload: Option<KVWorkload>,
+        history_recipient: Option<ActorPath>,
+        transfer_leader_at: u64,
+        discover_leader: bool,
         leader_election_latch: Arc<CountdownEvent>,
         finished_latch: Arc<CountdownEvent>,
     ) -> Client {
         println!("CREATING CLIENT");
         Client {
             ctx: ComponentContext::uninitialised(),
+            group,
+            num_groups,
             num_proposals,
             num_concurrent_proposals,
+            read_ratio,
+            reads: HashSet::new(),
+            workload,
+            commands: HashMap::new(),
+            kv_values: HashMap::new(),
+            history_recipient,
+            history: vec![],
+            transfer_leader_at,
+            pending_transfer: None,
+            learners_joined: None,
             nodes,
-            reconfig,
+            reconfigs: VecDeque::from(reconfigs),
+            discover_leader,
             leader_election_latch,
             finished_latch,
             latest_proposal_id: 0,
             responses: HashMap::with_capacity(num_proposals as usize),
+            lowest_pending: 1,
             pending_proposals: HashMap::with_capacity(num_concurrent_proposals as usize),
             timeout,
             current_leader: 0,
@@ -150,17 +194,190 @@ impl Client {
         }
     }
 
+    // the key of the client's id-th proposal, every num_groups-th key belongs to this group
+    fn key(&self, id: u64) -> u64 {
+        (id - 1) * self.num_groups + self.group + 1
+    }
+
+    fn local_id(&self, key: u64) -> u64 {
+        if key == RECONFIG_ID {
+            return RECONFIG_ID;
+        }
+        (key - 1) / self.num_groups + 1
+    }
+
+    fn next_command(&self, id: u64, rng: &mut impl Rng) -> KVCommand {
+        let workload = self.workload.as_ref().expect("No kv workload");
+        // keys are picked among the ones of this group, values are unique per operation
+        let key = self.key(rng.gen_range(1, workload.num_keys + 1));
+        let value = self.key(id) as u32;
+        let r: f64 = rng.gen();
+        if r < workload.get_ratio {
+            KVCommand::Get(key)
+        } else if r < workload.get_ratio + workload.cas_ratio {
+            let expected = self.kv_values.get(&key).copied().unwrap_or(0);
+            KVCommand::Cas(key, expected, value)
+        } else {
+            KVCommand::Put(key, value)
+        }
+    }
+
+    // each operation is its own sender in the history, so invocations and responses pair up
+    fn record(&mut self, id: u64, key: u64, operation: KVOperation, value: Option<u32>) {
+        if self.history_recipient.is_none() {
+            return;
+        }
+        let time = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .expect("Failed to get time since epoch")
+            .as_nanos() as i64;
+        let ts = KVTimestamp {
+            key,
+            operation,
+            value,
+            time,
+            sender: self.key(id) as u32,
+        };
+        self.history.push(ts);
+    }
+
+    fn record_invocation(&mut self, id: u64, command: &KVCommand) {
+        match *command {
+            KVCommand::Get(key) => self.record(id, key, KVOperation::ReadInvokation, None),
+            KVCommand::Put(key, value) => {
+                self.record(id, key, KVOperation::WriteInvokation, Some(value))
+            }
+            KVCommand::Cas(key, expected, new) => {
+                self.record(id, key, KVOperation::CasInvokation(expected), Some(new))
+            }
+            KVCommand::Append => {}
+        }
+    }
+
+    fn handle_kv_response(&mut self, id: u64, response: KVResponse) {
+        let command = match self.commands.remove(&id) {
+            Some(command) => command,
+            None => return,
+        };
+        match (command, response) {
+            (KVCommand::Get(key), KVResponse::Value(value)) => {
+                match value {
+                    Some(value) => self.kv_values.insert(key, value),
+                    None => self.kv_values.remove(&key),
+                };
+                self.record(id, key, KVOperation::ReadResponse, value);
+            }
+            (KVCommand::Put(key, value), KVResponse::Ok) => {
+                self.kv_values.insert(key, value);
+                self.record(id, key, KVOperation::WriteResponse, Some(value));
+            }
+            (KVCommand::Cas(key, _, new), KVResponse::Cas(swapped)) => {
+                if swapped {
+                    self.kv_values.insert(key, new);
+                }
+                self.record(id, key, KVOperation::CasResponse(swapped), Some(new));
+            }
+            (command, response) => panic!(
+                "Got unexpected response {:?} to command {:?}",
+                response, command
+            ),
+        }
+    }
+
     fn propose_normal(&self, id: u64, node: &ActorPath) {
-        let mut data: Vec<u8> = Vec::with_capacity(8);
-        data.put_u64(id);
+        let mut data: Vec<u8> = Vec::with_capacity(29);
+        let command = self.commands.get(&id).unwrap_or(&KVCommand::Append);
+        command.serialise(self.key(id), self.key(self.lowest_pending), &mut data);
         let p = Proposal::normal(data);
         node.tell_serialised(AtomicBroadcastMsg::Proposal(p), self)
             .expect("Should serialise Proposal");
     }
 
+    fn read(&self, id: u64, node: &ActorPath) {
+        node.tell_serialised(AtomicBroadcastMsg::ReadRequest(self.key(id)), self)
+            .expect("Should serialise ReadRequest");
+    }
+
+    fn send_operation(&self, id: u64, node: &ActorPath) {
+        if self.reads.contains(&id) {
+            self.read(id, node);
+        } else {
+            self.propose_normal(id, node);
+        }
+    }
+
+    fn handle_rejection(&mut self, rejection: ProposalRejected) {
+        let id = self.local_id(rejection.id);
+        let timer = match self.pending_proposals.get(&id) {
+            Some(proposal_meta) => proposal_meta.timer.clone(),
+            None => return,
+        };
+        self.cancel_timer(timer);
+        match rejection.reason {
+            RejectReason::NotLeader(hint) if hint != 0 && self.current_config.contains(&hint) => {
+                if self.current_leader != hint {
+                    self.current_leader = hint;
+                    self.leader_changes.push(hint);
+                    #[cfg(feature = "track_timestamps")]
+                    {
+                        self.leader_changes_t.push(self.clock.now());
+                    }
+                }
+                self.retry_rejected(id);
+            }
+            _ => {
+                let timer =
+                    self.schedule_once(REJECTED_RETRY_DELAY, move |c, _| c.retry_rejected(id));
+                self.pending_proposals
+                    .get_mut(&id)
+                    .expect("Rejected proposal not in pending proposals")
+                    .set_timer(timer);
+            }
+        }
+    }
+
+    fn retry_rejected(&mut self, id: u64) -> Handled {
+        if !self.pending_proposals.contains_key(&id) {
+            return Handled::Ok;
+        }
+        if let Some(leader) = self.nodes.get(&self.current_leader) {
+            self.send_operation(id, leader);
+        }
+        let timer = self.schedule_once(self.timeout, move |c, _| c.proposal_timeout(id));
+        self.pending_proposals
+            .get_mut(&id)
+            .expect("Rejected proposal not in pending proposals")
+            .set_timer(timer);
+        Handled::Ok
+    }
+
+    fn transfer_leader(&mut self) {
+        // hand over to the node after the current leader in the configuration
+        let n = self.current_config.len();
+        let pos = self
+            .current_config
+            .iter()
+            .position(|pid| *pid == self.current_leader)
+            .unwrap_or(n - 1);
+        let target = self.current_config[(pos + 1) % n];
+        if target == self.current_leader {
+            return;
+        }
+        info!(
+            self.ctx.log(),
+            "Transferring leadership from {} to {}", self.current_leader, target
+        );
+        if let Some(leader) = self.nodes.get(&self.current_leader) {
+            leader
+                .tell_serialised(AtomicBroadcastMsg::TransferLeader(target), self)
+                .expect("Should serialise TransferLeader");
+        }
+        self.pending_transfer = Some((target, SystemTime::now()));
+    }
+
     fn propose_reconfiguration(&self, node: &ActorPath) {
         println!("PROPOSING RECONFIG!!!!!!!!");
-        let reconfig = self.reconfig.as_ref().unwrap();
+        let (_, reconfig) = self.reconfigs.front().unwrap();
         debug!(
             self.ctx.log(),
             "{}",
@@ -178,6 +395,27 @@ impl Client {
         }
     }
 
+    fn propose_next_reconfiguration(&mut self) {
+        if self.state != ExperimentState::Running
+            || self.pending_proposals.contains_key(&RECONFIG_ID)
+        {
+            return;
+        }
+        match self.reconfigs.front() {
+            Some((at, _)) if *at <= self.responses.len() as u64 => {
+                if let Some(leader) = self.nodes.get(&self.current_leader) {
+                    self.propose_reconfiguration(&leader);
+                }
+                let timer =
+                    self.schedule_once(self.timeout, move |c, _| c.proposal_timeout(RECONFIG_ID));
+                let proposal_meta = ProposalMetaData::with(None, timer);
+                self.pending_proposals.insert(RECONFIG_ID, proposal_meta);
+                self.first_proposal_after_reconfig = None;
+            }
+            _ => {}
+        }
+    }
+
     fn send_concurrent_proposals(&mut self) {
         let num_inflight = self.pending_proposals.len() as u64;
         assert!(num_inflight <= self.num_concurrent_proposals);
@@ -200,12 +438,20 @@ impl Client {
             }
             let cache_start_time =
                 self.num_concurrent_proposals == 1 || cfg!(feature = "track_latency");
+            let mut rng = rand::thread_rng();
             for id in from..=to {
                 let current_time = match cache_start_time {
                     true => Some(SystemTime::now()),
                     _ => None,
                 };
-                self.propose_normal(id, &leader);
+                if self.read_ratio > 0.0 && rng.gen_bool(self.read_ratio) {
+                    self.reads.insert(id);
+                } else if self.workload.is_some() {
+                    let command = self.next_command(id, &mut rng);
+                    self.record_invocation(id, &command);
+                    self.commands.insert(id, command);
+                }
+                self.send_operation(id, &leader);
                 let timer = self.schedule_once(self.timeout, move |c, _| c.proposal_timeout(id));
                 let proposal_meta = ProposalMetaData::with(current_time, timer);
                 self.pending_proposals.insert(id, proposal_meta);
@@ -228,7 +474,7 @@ impl Client {
                 info!(self.ctx.log(), "Retrying proposals to node {}. Count: {}, min: {:?}, max: {:?}, num_pending: {}", self.current_leader, count, min, max, num_pending);
             }
             for (id, start_time) in retry_proposals {
-                self.propose_normal(id, &leader);
+                self.send_operation(id, &leader);
                 let timer = self.schedule_once(self.timeout, move |c, _| c.proposal_timeout(id));
                 let meta = ProposalMetaData::with(start_time, timer);
                 self.pending_proposals.insert(id, meta);
@@ -244,8 +490,14 @@ impl Client {
             self.timestamps.insert(id, timestamp);
         }
         self.responses.insert(id, latency_res);
+        while self.responses.contains_key(&self.lowest_pending) {
+            self.lowest_pending += 1;
+        }
         let received_count = self.responses.len() as u64;
-        if received_count == self.num_proposals && self.reconfig.is_none() {
+        if self.transfer_leader_at > 0 && received_count == self.transfer_leader_at {
+            self.transfer_leader();
+        }
+        if received_count == self.num_proposals && self.reconfigs.is_empty() {
             self.state = ExperimentState::Finished;
             self.finished_latch
                 .decrement()
@@ -277,14 +529,8 @@ impl Client {
                     self.current_leader
                 );
             }
-        } else if received_count == self.num_proposals / 2 && self.reconfig.is_some() {
-            if let Some(leader) = self.nodes.get(&self.current_leader) {
-                self.propose_reconfiguration(&leader);
-            }
-            let timer =
-                self.schedule_once(self.timeout, move |c, _| c.proposal_timeout(RECONFIG_ID));
-            let proposal_meta = ProposalMetaData::with(None, timer);
-            self.pending_proposals.insert(RECONFIG_ID, proposal_meta);
+        } else {
+            self.propose_next_reconfiguration();
         }
     }
 
@@ -307,6 +553,7 @@ impl Client {
             proposal_meta.set_timer(timer);
         } else {
             self.num_timed_out += 1;
+            self.commands.remove(&id);
             let proposal_meta = self
                 .pending_proposals
                 .remove(&id)
@@ -339,7 +586,7 @@ impl Client {
                 None => {
                     assert!(
                         self.responses.contains_key(&i),
-                        "Hold back proposal not in pending and responses: {}. State {:?}, has_reconfig: {}", i, self.state, self.reconfig.is_some()
+                        "Hold back proposal not in pending and responses: {}. State {:?}, remaining reconfigs: {}", i, self.state, self.reconfigs.len()
                     )
                 }
             }
@@ -354,9 +601,16 @@ impl Client {
                 for _ in 0..len {
                     config.push(data.get_u64());
                 }
-                Response::Reconfiguration(config)
+                let mut learners = vec![];
+                if data.remaining() >= 4 {
+                    let learners_len = data.get_u32();
+                    for _ in 0..learners_len {
+                        learners.push(data.get_u64());
+                    }
+                }
+                Response::Reconfiguration(config, learners)
             }
-            n => Response::Normal(n),
+            n => Response::Normal(n, KVResponse::deserialise(data)),
         }
     }
 
@@ -368,14 +622,30 @@ impl Client {
     }
 
     fn reply_stop_ask(&mut self) {
+        if let Some(recipient) = &self.history_recipient {
+            let history = std::mem::take(&mut self.history);
+            recipient
+                .tell_serialised(PartitioningActorMsg::TestDone(history), self)
+                .expect("Should serialise TestDone");
+        }
         let l = std::mem::take(&mut self.responses);
         let mut v: Vec<_> = l
             .into_iter()
             .filter(|(_, latency)| latency.is_some())
             .collect();
         v.sort();
-        let latencies: Vec<Duration> = v.into_iter().map(|(_, latency)| latency.unwrap()).collect();
-        let mut meta_results = MetaResults::with(self.num_timed_out, latencies, None);
+        let (reads, writes): (Vec<_>, Vec<_>) =
+            v.into_iter().partition(|(id, _)| self.reads.contains(id));
+        let latencies: Vec<Duration> = writes
+            .into_iter()
+            .map(|(_, latency)| latency.unwrap())
+            .collect();
+        let read_latencies: Vec<Duration> = reads
+            .into_iter()
+            .map(|(_, latency)| latency.unwrap())
+            .collect();
+        let mut meta_results =
+            MetaResults::with(self.num_timed_out, latencies, read_latencies, None);
         #[cfg(feature = "track_timestamps")]
         {
             let mut ts: Vec<_> = std::mem::take(&mut self.timestamps).into_iter().collect();
@@ -411,6 +681,10 @@ impl Actor for Client {
         match msg {
             LocalClientMessage::Run => {
                 self.state = ExperimentState::Running;
+                if self.discover_leader && self.current_leader == 0 {
+                    // any node will do, followers redirect to the leader
+                    self.current_leader = self.current_config[0];
+                }
                 assert_ne!(self.current_leader, 0);
                 #[cfg(feature = "track_timestamps")]
                 {
@@ -449,11 +723,15 @@ impl Actor for Client {
                         if !self.current_config.contains(&pid) { return Handled::Ok; }
                         match self.state {
                             ExperimentState::LeaderElection => {
+                                // the latch is shared by the clients of all groups, count only once
+                                let first_leader = self.current_leader == 0;
                                 self.current_leader = pid;
-                                match self.leader_election_latch.decrement() {
-                                    Ok(_) => info!(self.ctx.log(), "Got first leader: {}", pid),
-                                    Err(e) => if e != CountdownError::AlreadySet {
-                                        panic!("Failed to decrement election latch: {:?}", e);
+                                if first_leader {
+                                    match self.leader_election_latch.decrement() {
+                                        Ok(_) => info!(self.ctx.log(), "Got first leader: {}", pid),
+                                        Err(e) => if e != CountdownError::AlreadySet {
+                                            panic!("Failed to decrement election latch: {:?}", e);
+                                        }
                                     }
                                 }
                             },
@@ -470,8 +748,25 @@ impl Actor for Client {
                                 if self.retry_proposals.is_empty() {
                                     self.first_proposal_after_reconfig = Some(self.latest_proposal_id);
                                 }
+                                self.propose_next_reconfiguration();
                                 self.send_concurrent_proposals();
                             },
+                            ExperimentState::Running => {
+                                if let Some((target, start_time)) = self.pending_transfer {
+                                    if target != pid { return Handled::Ok; }
+                                    self.pending_transfer = None;
+                                    let handover = start_time.elapsed().expect("Failed to get elapsed duration");
+                                    info!(self.ctx.log(), "Leadership transferred from {} to {} in {:?}", self.current_leader, pid, handover);
+                                    if self.current_leader != pid {
+                                        self.current_leader = pid;
+                                        self.leader_changes.push(pid);
+                                        #[cfg(feature = "track_timestamps")] {
+                                            self.leader_changes_t.push(self.clock.now());
+                                        }
+                                    }
+                                    self.send_concurrent_proposals();
+                                }
+                            },
                             _ => {},
                         }
                     },
@@ -480,8 +775,10 @@ impl Actor for Client {
                         let data = pr.data;
                         let response = Self::deserialise_response(&mut data.as_slice());
                         match response {
-                            Response::Normal(id) => {
+                            Response::Normal(key, kv_response) => {
+                                let id = self.local_id(key);
                                 if let Some(proposal_meta) = self.pending_proposals.remove(&id) {
+                                    self.handle_kv_response(id, kv_response);
                                     let latency = match proposal_meta.start_time {
                                         Some(start_time) => Some(start_time.elapsed().expect("Failed to get elapsed duration")),
                                         _ => None,
@@ -509,15 +806,27 @@ impl Actor for Client {
                                     }
                                 }
                             }
-                            Response::Reconfiguration(new_config) => {
+                            Response::Reconfiguration(new_config, learners) => {
+                                if !learners.is_empty() && self.pending_proposals.contains_key(&RECONFIG_ID) {
+                                    // the new nodes are catching up, the reconfiguration completes when they are promoted
+                                    if self.learners_joined.is_none() {
+                                        info!(self.ctx.log(), "Learners {:?} joined, voters: {:?}", learners, new_config);
+                                        self.learners_joined = Some(SystemTime::now());
+                                    }
+                                    return Handled::Ok;
+                                }
                                 if let Some(proposal_meta) = self.pending_proposals.remove(&RECONFIG_ID) {
+                                    if let Some(joined) = self.learners_joined.take() {
+                                        let catch_up = joined.elapsed().expect("Failed to get elapsed duration");
+                                        info!(self.ctx.log(), "Learners promoted after catching up for {:?}, voters: {:?}", catch_up, new_config);
+                                    }
                                     self.cancel_timer(proposal_meta.timer);
-                                    if self.responses.len() as u64 == self.num_proposals {
+                                    self.reconfigs.pop_front();
+                                    if self.responses.len() as u64 == self.num_proposals && self.reconfigs.is_empty() {
                                         self.state = ExperimentState::Finished;
                                         self.finished_latch.decrement().expect("Failed to countdown finished latch");
                                         info!(self.ctx.log(), "Got reconfig at last. {} proposals timed out. Leader changes: {}, {:?}, Last leader was: {}", self.num_timed_out, self.leader_changes.len(), self.leader_changes, self.current_leader);
                                     } else {
-                                        self.reconfig = None;
                                         self.current_config = new_config;
                                         let leader_changed = self.current_leader != pr.latest_leader;
                                         info!(self.ctx.log(), "Reconfig OK, leader: {}, old: {}, current_config: {:?}", pr.latest_leader, self.current_leader, self.current_config);
@@ -526,6 +835,7 @@ impl Actor for Client {
                                             self.state = ExperimentState::ReconfigurationElection;
                                         } else {    // Raft: continue if there is a leader
                                             self.state = ExperimentState::Running;
+                                            self.propose_next_reconfiguration();
                                             self.send_concurrent_proposals();
                                             if leader_changed {
                                                 self.leader_changes.push(pr.latest_leader);
@@ -539,11 +849,30 @@ impl Actor for Client {
                             }
                         }
                     },
+                    AtomicBroadcastMsg::ReadResp(rr) => {
+                        if self.state == ExperimentState::Finished || self.state == ExperimentState::LeaderElection { return Handled::Ok; }
+                        let id = self.local_id(rr.id);
+                        if let Some(proposal_meta) = self.pending_proposals.remove(&id) {
+                            let latency = match proposal_meta.start_time {
+                                Some(start_time) => Some(start_time.elapsed().expect("Failed to get elapsed duration")),
+                                _ => None,
+                            };
+                            self.cancel_timer(proposal_meta.timer);
+                            self.handle_normal_response(id, latency);
+                            if self.state != ExperimentState::ReconfigurationElection {
+                                self.send_concurrent_proposals();
+                            }
+                        }
+                    }
+                    AtomicBroadcastMsg::ProposalRejected(pr) => {
+                        if self.state == ExperimentState::Finished || self.state == ExperimentState::LeaderElection { return Handled::Ok; }
+                        self.handle_rejection(pr);
+                    }
                     AtomicBroadcastMsg::PendingReconfiguration(data) => {
                         let dropped_proposal = data.as_slice().get_u64();
                         match self.state {
                             ExperimentState::Running => {
-                                if self.reconfig.is_some() {    // still running in old config
+                                if self.pending_proposals.contains_key(&RECONFIG_ID) {    // still running in old config
                                     self.hold_back_proposals(dropped_proposal, self.latest_proposal_id);
                                     self.state = ExperimentState::ReconfigurationElection;  // wait for FirstLeader in new configuration before proposing more
                                 } else {    // already running in new configuration
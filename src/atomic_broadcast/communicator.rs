@@ -2,24 +2,33 @@ extern crate raft as tikv_raft;
 
 use crate::atomic_broadcast::{
     messages::{
+        paxos::{BLEMessage, BLESer, Ballot, PaxosMsgWrapper, PaxosSer},
         raft::{RaftMsg, RawRaftSer},
-        AtomicBroadcastMsg, ProposalResp, StopMsg as NetStopMsg, StopMsgDeser,
+        AtomicBroadcastMsg, ProposalRejected, ProposalResp, ReadResp, StopMsg as NetStopMsg,
+        StopMsgDeser,
     },
 };
 use hashbrown::HashMap;
 use kompact::prelude::*;
+use leaderpaxos::messages::Message as RawPaxosMsg;
 use tikv_raft::prelude::Message as RawRaftMsg;
 
 #[derive(Clone, Debug)]
 pub enum AtomicBroadcastCompMsg {
-    RawRaftMsg(RawRaftMsg),
+    RawRaftMsg(u64, RawRaftMsg), // (group, msg), every replica gets it and handles its own group's
+    RawPaxosMsg(RawPaxosMsg<Ballot>),
+    BLEMsg(BLEMessage),
     StopMsg(u64),
 }
 
 #[derive(Clone, Debug)]
 pub enum CommunicatorMsg {
-    RawRaftMsg(RawRaftMsg),
-    ProposalResponse(ProposalResp),
+    RawRaftMsg(u64, RawRaftMsg),
+    RawPaxosMsg(RawPaxosMsg<Ballot>),
+    BLEMsg(BLEMessage),
+    ProposalResponse(u64, ProposalResp),
+    ReadResponse(u64, ReadResp),
+    ProposalRejected(u64, ProposalRejected),
     SendStop(u64, bool),
 }
 
@@ -35,18 +44,36 @@ pub struct Communicator {
     ctx: ComponentContext<Communicator>,
     atomic_broadcast_port: ProvidedPort<CommunicationPort>,
     peers: HashMap<u64, ActorPath>, // node id -> actorpath
-    client: ActorPath,              // cached client to send SequenceResp to
+    clients: Vec<ActorPath>,        // cached client of each group to send SequenceResp to
+    stop_requests: usize,           // groups that have stopped, the peers are told once all have
 }
 
 impl Communicator {
-    pub fn with(peers: HashMap<u64, ActorPath>, client: ActorPath) -> Communicator {
+    pub fn with(peers: HashMap<u64, ActorPath>, clients: Vec<ActorPath>) -> Communicator {
         Communicator {
             ctx: ComponentContext::uninitialised(),
             atomic_broadcast_port: ProvidedPort::uninitialised(),
             peers,
-            client,
+            clients,
+            stop_requests: 0,
         }
     }
+
+    fn peer(&self, pid: u64) -> &ActorPath {
+        self.peers.get(&pid).unwrap_or_else(|| {
+            panic!(
+                "Could not find actorpath for id={}. Known peers: {:?}",
+                pid,
+                self.peers.keys()
+            )
+        })
+    }
+
+    fn client(&self, group: u64) -> &ActorPath {
+        self.clients
+            .get(group as usize)
+            .unwrap_or_else(|| panic!("No client for group {}", group))
+    }
 }
 
 ignore_lifecycle!(Communicator);
@@ -54,7 +81,7 @@ ignore_lifecycle!(Communicator);
 impl Provide<CommunicationPort> for Communicator {
     fn handle(&mut self, msg: CommunicatorMsg) -> Handled {
         match msg {
-            CommunicatorMsg::RawRaftMsg(rm) => {
+            CommunicatorMsg::RawRaftMsg(group, rm) => {
                 let receiver = self.peers.get(&rm.get_to()).unwrap_or_else(|| {
                     panic!(
                         "Could not find actorpath for id={}. Known peers: {:?}. RaftMsg: {:?}",
@@ -64,26 +91,58 @@ impl Provide<CommunicationPort> for Communicator {
                     )
                 });
                 receiver
-                    .tell_serialised(RaftMsg(rm), self)
+                    .tell_serialised(RaftMsg(group, rm), self)
                     .expect("Should serialise RaftMsg");
             }
-            CommunicatorMsg::ProposalResponse(pr) => {
+            CommunicatorMsg::RawPaxosMsg(pm) => {
+                let receiver = self.peer(pm.to);
+                receiver
+                    .tell_serialised(PaxosMsgWrapper(pm), self)
+                    .expect("Should serialise PaxosMsg");
+            }
+            CommunicatorMsg::BLEMsg(bm) => {
+                let receiver = self.peer(bm.to);
+                receiver
+                    .tell_serialised(bm, self)
+                    .expect("Should serialise BLEMessage");
+            }
+            CommunicatorMsg::ProposalResponse(group, pr) => {
                 trace!(self.ctx.log(), "ProposalResp: {:?}", pr);
                 let am = AtomicBroadcastMsg::ProposalResp(pr);
-                self.client
+                self.client(group)
                     .tell_serialised(am, self)
                     .expect("Should serialise ProposalResp");
             }
+            CommunicatorMsg::ReadResponse(group, rr) => {
+                trace!(self.ctx.log(), "ReadResp: {:?}", rr);
+                let am = AtomicBroadcastMsg::ReadResp(rr);
+                self.client(group)
+                    .tell_serialised(am, self)
+                    .expect("Should serialise ReadResp");
+            }
+            CommunicatorMsg::ProposalRejected(group, pr) => {
+                trace!(self.ctx.log(), "ProposalRejected: {:?}", pr);
+                let am = AtomicBroadcastMsg::ProposalRejected(pr);
+                self.client(group)
+                    .tell_serialised(am, self)
+                    .expect("Should serialise ProposalRejected");
+            }
             CommunicatorMsg::SendStop(my_pid, ack_client) => {
+                self.stop_requests += 1;
+                if self.stop_requests < self.clients.len() {
+                    return Handled::Ok;
+                }
                 debug!(self.ctx.log(), "Sending stop to {:?}", self.peers.keys());
                 for ap in self.peers.values() {
                     ap.tell_serialised(NetStopMsg::Peer(my_pid), self)
                         .expect("Should serialise StopMsg")
                 }
                 if ack_client {
-                    self.client
-                        .tell_serialised(NetStopMsg::Peer(my_pid), self)
-                        .expect("Should serialise StopMsg")
+                    for client in &self.clients {
+                        client
+                            .tell_serialised(NetStopMsg::Peer(my_pid), self)
+                            .expect("Should serialise StopMsg")
+                    }
                 }
             }
         }
@@ -102,8 +161,15 @@ impl Actor for Communicator {
     fn receive_network(&mut self, m: NetMessage) -> Handled {
         let NetMessage { data, .. } = m;
         match_deser! {data {
-            msg(r): RawRaftMsg [using RawRaftSer] => {
-                self.atomic_broadcast_port.trigger(AtomicBroadcastCompMsg::RawRaftMsg(r));
+            msg(r): RaftMsg [using RawRaftSer] => {
+                let RaftMsg(group, rm) = r;
+                self.atomic_broadcast_port.trigger(AtomicBroadcastCompMsg::RawRaftMsg(group, rm));
+            },
+            msg(p): RawPaxosMsg<Ballot> [using PaxosSer] => {
+                self.atomic_broadcast_port.trigger(AtomicBroadcastCompMsg::RawPaxosMsg(p));
+            },
+            msg(b): BLEMessage [using BLESer] => {
+                self.atomic_broadcast_port.trigger(AtomicBroadcastCompMsg::BLEMsg(b));
             },
             msg(stop): NetStopMsg [using StopMsgDeser] => {
                 if let NetStopMsg::Peer(pid) = stop {
@@ -111,7 +177,7 @@ impl Actor for Communicator {
                 }
             },
             err(e) => error!(self.ctx.log(), "Error deserialising msg: {:?}", e),
-            default(_) => unimplemented!("Should be either RawRaftMsg, PaxosMsg or NetStopMsg!")
+            default(_) => unimplemented!("Should be either RawRaftMsg, PaxosMsg, BLEMessage or NetStopMsg!")
         }
         }
         Handled::Ok
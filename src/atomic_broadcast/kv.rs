@@ -0,0 +1,275 @@
+use hashbrown::HashMap;
+use kompact::prelude::{Buf, BufMut};
+use std::collections::BTreeMap;
+
+/* A proposal's data is one or more commands, each encoded as: id (u64) | watermark (u64) | op (u8) | args
+The watermark is the lowest id the client still awaits a response for. */
+const APPEND_ID: u8 = 1;
+const GET_ID: u8 = 2;
+const PUT_ID: u8 = 3;
+const CAS_ID: u8 = 4;
+
+const OK_ID: u8 = 1;
+const VALUE_ID: u8 = 2;
+const NOT_FOUND_ID: u8 = 3;
+const CAS_RESULT_ID: u8 = 4;
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum KVCommand {
+    Append, // plain log workload, leaves the store untouched
+    Get(u64),
+    Put(u64, u32),
+    Cas(u64, u32, u32), // (key, expected, new)
+}
+
+impl KVCommand {
+    pub fn serialise(&self, id: u64, watermark: u64, buf: &mut dyn BufMut) {
+        buf.put_u64(id);
+        buf.put_u64(watermark);
+        match self {
+            KVCommand::Append => buf.put_u8(APPEND_ID),
+            KVCommand::Get(key) => {
+                buf.put_u8(GET_ID);
+                buf.put_u64(*key);
+            }
+            KVCommand::Put(key, value) => {
+                buf.put_u8(PUT_ID);
+                buf.put_u64(*key);
+                buf.put_u32(*value);
+            }
+            KVCommand::Cas(key, expected, new) => {
+                buf.put_u8(CAS_ID);
+                buf.put_u64(*key);
+                buf.put_u32(*expected);
+                buf.put_u32(*new);
+            }
+        }
+    }
+
+    fn deserialise(buf: &mut dyn Buf) -> KVCommand {
+        match buf.get_u8() {
+            APPEND_ID => KVCommand::Append,
+            GET_ID => KVCommand::Get(buf.get_u64()),
+            PUT_ID => KVCommand::Put(buf.get_u64(), buf.get_u32()),
+            CAS_ID => KVCommand::Cas(buf.get_u64(), buf.get_u32(), buf.get_u32()),
+            op => panic!("Found unknown KVCommand id: {}", op),
+        }
+    }
+}
+
+// the (id, watermark, command) triples of a proposal, e.g. several for a batched entry
+pub fn deserialise_commands(data: &[u8]) -> Vec<(u64, u64, KVCommand)> {
+    let mut buf = data;
+    let mut commands = vec![];
+    while buf.remaining() >= 17 {
+        let id = buf.get_u64();
+        let watermark = buf.get_u64();
+        commands.push((id, watermark, KVCommand::deserialise(&mut buf)));
+    }
+    commands
+}
+
+pub fn command_ids(data: &[u8]) -> Vec<u64> {
+    deserialise_commands(data)
+        .into_iter()
+        .map(|(id, _, _)| id)
+        .collect()
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum KVResponse {
+    Ok,
+    Value(Option<u32>),
+    Cas(bool), // whether the swap was applied
+}
+
+impl KVResponse {
+    pub fn serialise(&self, buf: &mut dyn BufMut) {
+        match self {
+            KVResponse::Ok => buf.put_u8(OK_ID),
+            KVResponse::Value(Some(value)) => {
+                buf.put_u8(VALUE_ID);
+                buf.put_u32(*value);
+            }
+            KVResponse::Value(None) => buf.put_u8(NOT_FOUND_ID),
+            KVResponse::Cas(swapped) => {
+                buf.put_u8(CAS_RESULT_ID);
+                buf.put_u8(*swapped as u8);
+            }
+        }
+    }
+
+    pub fn deserialise(buf: &mut dyn Buf) -> KVResponse {
+        match buf.get_u8() {
+            OK_ID => KVResponse::Ok,
+            VALUE_ID => KVResponse::Value(Some(buf.get_u32())),
+            NOT_FOUND_ID => KVResponse::Value(None),
+            CAS_RESULT_ID => KVResponse::Cas(buf.get_u8() != 0),
+            r => panic!("Found unknown KVResponse id: {}", r),
+        }
+    }
+}
+
+// The state machine every replica applies the committed commands to, one client proposes to it
+#[derive(Clone, Debug, Default)]
+pub struct KVStore {
+    values: HashMap<u64, u32>,
+    responses: BTreeMap<u64, KVResponse>, // applied command ids at or above the watermark, a retried command is not applied twice
+    watermark: u64,                       // the client has the responses of all ids below it
+}
+
+impl KVStore {
+    // None if the command is a duplicate the client no longer awaits
+    pub fn apply(&mut self, id: u64, watermark: u64, command: &KVCommand) -> Option<KVResponse> {
+        if watermark > self.watermark {
+            self.watermark = watermark;
+            while let Some(lowest) = self.responses.keys().next().copied() {
+                if lowest >= watermark {
+                    break;
+                }
+                self.responses.remove(&lowest);
+            }
+        }
+        if let Some(response) = self.responses.get(&id) {
+            return Some(response.clone());
+        }
+        if id < self.watermark {
+            return None;
+        }
+        let response = match command {
+            KVCommand::Append => return Some(KVResponse::Ok),
+            KVCommand::Get(key) => KVResponse::Value(self.values.get(key).copied()),
+            KVCommand::Put(key, value) => {
+                self.values.insert(*key, *value);
+                KVResponse::Ok
+            }
+            KVCommand::Cas(key, expected, new) => {
+                let swapped = self.values.get(key) == Some(expected);
+                if swapped {
+                    self.values.insert(*key, *new);
+                }
+                KVResponse::Cas(swapped)
+            }
+        };
+        self.responses.insert(id, response.clone());
+        Some(response)
+    }
+
+    pub fn serialise(&self, buf: &mut dyn BufMut) {
+        buf.put_u64(self.values.len() as u64);
+        for (key, value) in &self.values {
+            buf.put_u64(*key);
+            buf.put_u32(*value);
+        }
+        buf.put_u64(self.watermark);
+        buf.put_u64(self.responses.len() as u64);
+        for (id, response) in &self.responses {
+            buf.put_u64(*id);
+            response.serialise(buf);
+        }
+    }
+
+    pub fn deserialise(buf: &mut dyn Buf) -> KVStore {
+        let mut store = KVStore::default();
+        let values_len = buf.get_u64();
+        for _ in 0..values_len {
+            let key = buf.get_u64();
+            store.values.insert(key, buf.get_u32());
+        }
+        store.watermark = buf.get_u64();
+        let responses_len = buf.get_u64();
+        for _ in 0..responses_len {
+            let id = buf.get_u64();
+            store.responses.insert(id, KVResponse::deserialise(buf));
+        }
+        store
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct KVWorkload {
+    pub num_keys: u64, // keys per Raft group
+    pub get_ratio: f64,
+    pub cas_ratio: f64, // the remaining operations are puts
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn commands_roundtrip() {
+        let commands = vec![
+            (1, 1, KVCommand::Append),
+            (2, 1, KVCommand::Get(7)),
+            (3, 2, KVCommand::Put(7, 3)),
+            (4, 2, KVCommand::Cas(7, 3, 4)),
+        ];
+        let mut data: Vec<u8> = vec![];
+        for (id, watermark, command) in &commands {
+            command.serialise(*id, *watermark, &mut data);
+        }
+        assert_eq!(deserialise_commands(&data), commands);
+        assert_eq!(command_ids(&data), vec![1, 2, 3, 4]);
+    }
+
+    #[test]
+    fn apply_commands() {
+        let mut store = KVStore::default();
+        assert_eq!(
+            store.apply(1, 1, &KVCommand::Get(7)),
+            Some(KVResponse::Value(None))
+        );
+        assert_eq!(
+            store.apply(2, 2, &KVCommand::Cas(7, 1, 2)),
+            Some(KVResponse::Cas(false))
+        );
+        assert_eq!(
+            store.apply(3, 3, &KVCommand::Put(7, 1)),
+            Some(KVResponse::Ok)
+        );
+        assert_eq!(
+            store.apply(4, 3, &KVCommand::Cas(7, 1, 2)),
+            Some(KVResponse::Cas(true))
+        );
+        assert_eq!(
+            store.apply(5, 3, &KVCommand::Get(7)),
+            Some(KVResponse::Value(Some(2)))
+        );
+        // a retried put is answered without overwriting the newer value
+        assert_eq!(
+            store.apply(3, 3, &KVCommand::Put(7, 1)),
+            Some(KVResponse::Ok)
+        );
+        assert_eq!(
+            store.apply(6, 6, &KVCommand::Get(7)),
+            Some(KVResponse::Value(Some(2)))
+        );
+        // below the watermark the responses are dropped and a late duplicate is not applied
+        assert_eq!(store.responses.len(), 1);
+        assert_eq!(store.apply(3, 3, &KVCommand::Put(7, 1)), None);
+        assert_eq!(
+            store.apply(7, 6, &KVCommand::Get(7)),
+            Some(KVResponse::Value(Some(2)))
+        );
+    }
+
+    #[test]
+    fn store_roundtrip() {
+        let mut store = KVStore::default();
+        store.apply(1, 1, &KVCommand::Put(7, 1));
+        store.apply(2, 2, &KVCommand::Cas(7, 1, 2));
+        let mut data: Vec<u8> = vec![];
+        store.serialise(&mut data);
+        let mut restored = KVStore::deserialise(&mut data.as_slice());
+        assert_eq!(
+            restored.apply(3, 2, &KVCommand::Get(7)),
+            Some(KVResponse::Value(Some(2)))
+        );
+        assert_eq!(
+            restored.apply(2, 2, &KVCommand::Cas(7, 1, 2)),
+            Some(KVResponse::Cas(true))
+        );
+        assert_eq!(restored.apply(1, 2, &KVCommand::Put(7, 1)), None);
+    }
+}
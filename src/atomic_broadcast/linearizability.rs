@@ -0,0 +1,343 @@
+use super::partitioning_actor::{KVOperation, KVTimestamp};
+use hashbrown::{HashMap, HashSet};
+
+/* Checks kv histories for linearizability with a Wing & Gong search, memoized on the set of
+linearized operations and the resulting state as in Lowe's and Porcupine's variants. Keys are
+independent registers, so every key is checked on its own. */
+
+#[derive(Clone, Debug)]
+enum OpKind {
+    Read(Option<u32>),
+    Write(u32),
+    Cas(u32, u32, Option<bool>), // (expected, new, swapped), unknown if it never returned
+}
+
+#[derive(Clone, Debug)]
+struct Op {
+    call: i64,
+    ret: i64, // i64::MAX if it never returned, i.e. it may take effect any time after the call
+    kind: OpKind,
+}
+
+impl Op {
+    // the state after applying the op to the register, None if the op can't be applied to it
+    fn step(&self, state: Option<u32>) -> Option<Option<u32>> {
+        match self.kind {
+            OpKind::Read(value) if value == state => Some(state),
+            OpKind::Read(_) => None,
+            OpKind::Write(value) => Some(Some(value)),
+            OpKind::Cas(expected, new, swapped) => {
+                let swaps = state == Some(expected);
+                match swapped {
+                    Some(s) if s != swaps => None,
+                    _ if swaps => Some(Some(new)),
+                    _ => Some(state),
+                }
+            }
+        }
+    }
+
+    fn is_read_only(&self) -> bool {
+        matches!(self.kind, OpKind::Read(_) | OpKind::Cas(_, _, Some(false)))
+    }
+
+    fn observed(&self) -> Option<Option<u32>> {
+        match self.kind {
+            OpKind::Read(value) => Some(value),
+            _ => None,
+        }
+    }
+
+    // the value the op may write
+    fn written(&self) -> Option<u32> {
+        match self.kind {
+            OpKind::Write(value) => Some(value),
+            OpKind::Cas(_, _, Some(false)) => None,
+            OpKind::Cas(_, new, _) => Some(new),
+            OpKind::Read(_) => None,
+        }
+    }
+}
+
+// remaining reads per observed value and remaining ops that may write each value
+#[derive(Default)]
+struct Remaining {
+    reads: HashMap<Option<u32>, usize>,
+    writes: HashMap<u32, usize>,
+}
+
+impl Remaining {
+    fn add(&mut self, op: &Op) {
+        if let Some(value) = op.observed() {
+            *self.reads.entry(value).or_insert(0) += 1;
+        }
+        if let Some(value) = op.written() {
+            *self.writes.entry(value).or_insert(0) += 1;
+        }
+    }
+
+    fn remove(&mut self, op: &Op) {
+        if let Some(value) = op.observed() {
+            *self.reads.get_mut(&value).unwrap() -= 1;
+        }
+        if let Some(value) = op.written() {
+            *self.writes.get_mut(&value).unwrap() -= 1;
+        }
+    }
+
+    // whether a remaining read still has to see the state, which can't be written again
+    fn pins(&self, state: Option<u32>) -> bool {
+        let reads = self.reads.get(&state).copied().unwrap_or(0);
+        let writes = state.map_or(0, |s| self.writes.get(&s).copied().unwrap_or(0));
+        reads > 0 && writes == 0
+    }
+}
+
+// pairs up invocations and responses, each operation is issued by its own sender
+fn operations(history: &[KVTimestamp]) -> HashMap<u64, Vec<Op>> {
+    let mut calls: HashMap<u32, &KVTimestamp> = HashMap::new();
+    let mut returns: HashMap<u32, &KVTimestamp> = HashMap::new();
+    for ts in history {
+        match ts.operation {
+            KVOperation::ReadInvokation
+            | KVOperation::WriteInvokation
+            | KVOperation::CasInvokation(_) => {
+                assert!(
+                    calls.insert(ts.sender, ts).is_none(),
+                    "Found several invocations of sender {}",
+                    ts.sender
+                );
+            }
+            _ => {
+                assert!(
+                    returns.insert(ts.sender, ts).is_none(),
+                    "Found several responses of sender {}",
+                    ts.sender
+                );
+            }
+        }
+    }
+    let mut ops: HashMap<u64, Vec<Op>> = HashMap::new();
+    for (sender, call) in calls {
+        let ret = returns.remove(&sender);
+        let kind = match (call.operation, ret.map(|r| r.operation)) {
+            (KVOperation::ReadInvokation, Some(KVOperation::ReadResponse)) => {
+                OpKind::Read(ret.unwrap().value)
+            }
+            // a read that never returned constrains nothing
+            (KVOperation::ReadInvokation, None) => continue,
+            (KVOperation::WriteInvokation, Some(KVOperation::WriteResponse))
+            | (KVOperation::WriteInvokation, None) => {
+                OpKind::Write(call.value.expect("No value in write invocation"))
+            }
+            (KVOperation::CasInvokation(expected), Some(KVOperation::CasResponse(swapped))) => {
+                OpKind::Cas(
+                    expected,
+                    call.value.expect("No value in cas invocation"),
+                    Some(swapped),
+                )
+            }
+            (KVOperation::CasInvokation(expected), None) => OpKind::Cas(
+                expected,
+                call.value.expect("No value in cas invocation"),
+                None,
+            ),
+            (c, r) => panic!("Found response {:?} to invocation {:?}", r, c),
+        };
+        let op = Op {
+            call: call.time,
+            ret: ret.map_or(i64::MAX, |r| r.time),
+            kind,
+        };
+        ops.entry(call.key).or_insert_with(Vec::new).push(op);
+    }
+    assert!(
+        returns.is_empty(),
+        "Found responses without invocation: {:?}",
+        returns.keys()
+    );
+    ops
+}
+
+fn linearizable_register(mut ops: Vec<Op>) -> bool {
+    ops.sort_by_key(|op| op.call);
+    let n = ops.len();
+    let mut linearized = vec![0u64; (n + 63) / 64];
+    let mut cache: HashSet<(Vec<u64>, Option<u32>)> = HashSet::new();
+    let mut stack: Vec<(usize, Option<u32>, bool)> = Vec::with_capacity(n); // (linearized op, state before it, forced)
+    let mut remaining = Remaining::default();
+    for op in &ops {
+        remaining.add(op);
+    }
+    let mut state: Option<u32> = None;
+    let mut next = 0; // where the search for the next op continues after backtracking
+    let is_linearized = |linearized: &[u64], i: usize| linearized[i / 64] & (1u64 << (i % 64)) != 0;
+    while stack.len() < n {
+        // an op can go next if no other remaining op returned before it was called
+        let min_ret = (0..n)
+            .filter(|i| !is_linearized(&linearized, *i))
+            .map(|i| ops[i].ret)
+            .min()
+            .unwrap();
+        let mut found = None;
+        if next == 0 {
+            // a read-only op that holds in the current state can always go first, so there is
+            // no need to try the other ops in its place
+            let forced = (0..n).take_while(|i| ops[*i].call <= min_ret).find(|i| {
+                !is_linearized(&linearized, *i)
+                    && ops[*i].is_read_only()
+                    && ops[*i].step(state).is_some()
+            });
+            if let Some(i) = forced {
+                linearized[i / 64] |= 1u64 << (i % 64);
+                if cache.insert((linearized.clone(), state)) {
+                    found = Some((i, state, true));
+                } else {
+                    // already explored
+                    linearized[i / 64] &= !(1u64 << (i % 64));
+                    next = n;
+                }
+            }
+        }
+        if found.is_none() {
+            for i in next..n {
+                if ops[i].call > min_ret {
+                    break;
+                }
+                if is_linearized(&linearized, i) {
+                    continue;
+                }
+                if let Some(new_state) = ops[i].step(state) {
+                    if new_state != state && remaining.pins(state) {
+                        continue;
+                    }
+                    linearized[i / 64] |= 1u64 << (i % 64);
+                    if cache.insert((linearized.clone(), new_state)) {
+                        found = Some((i, new_state, false));
+                        break;
+                    }
+                    linearized[i / 64] &= !(1u64 << (i % 64));
+                }
+            }
+        }
+        match found {
+            Some((i, new_state, forced)) => {
+                remaining.remove(&ops[i]);
+                stack.push((i, state, forced));
+                state = new_state;
+                next = 0;
+            }
+            None => loop {
+                // backtrack to the latest op that had alternatives
+                match stack.pop() {
+                    Some((i, prev_state, forced)) => {
+                        remaining.add(&ops[i]);
+                        linearized[i / 64] &= !(1u64 << (i % 64));
+                        state = prev_state;
+                        if !forced {
+                            next = i + 1;
+                            break;
+                        }
+                    }
+                    None => return false,
+                }
+            },
+        }
+    }
+    true
+}
+
+// Err holds the first key whose operations are not linearizable
+pub fn check_history(history: &[KVTimestamp]) -> Result<(), u64> {
+    let mut ops: Vec<_> = operations(history).into_iter().collect();
+    ops.sort_by_key(|(key, _)| *key);
+    for (key, key_ops) in ops {
+        if !linearizable_register(key_ops) {
+            return Err(key);
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn ts(sender: u32, operation: KVOperation, value: Option<u32>, time: i64) -> KVTimestamp {
+        KVTimestamp {
+            key: 1,
+            operation,
+            value,
+            time,
+            sender,
+        }
+    }
+
+    #[test]
+    fn sequential_history() {
+        let history = vec![
+            ts(1, KVOperation::ReadInvokation, None, 0),
+            ts(1, KVOperation::ReadResponse, None, 1),
+            ts(2, KVOperation::WriteInvokation, Some(2), 2),
+            ts(2, KVOperation::WriteResponse, Some(2), 3),
+            ts(3, KVOperation::CasInvokation(2), Some(3), 4),
+            ts(3, KVOperation::CasResponse(true), Some(3), 5),
+            ts(4, KVOperation::ReadInvokation, None, 6),
+            ts(4, KVOperation::ReadResponse, Some(3), 7),
+        ];
+        assert_eq!(check_history(&history), Ok(()));
+    }
+
+    #[test]
+    fn stale_read() {
+        let history = vec![
+            ts(1, KVOperation::WriteInvokation, Some(1), 0),
+            ts(1, KVOperation::WriteResponse, Some(1), 1),
+            ts(2, KVOperation::WriteInvokation, Some(2), 2),
+            ts(2, KVOperation::WriteResponse, Some(2), 3),
+            ts(3, KVOperation::ReadInvokation, None, 4),
+            ts(3, KVOperation::ReadResponse, Some(1), 5),
+        ];
+        assert_eq!(check_history(&history), Err(1));
+    }
+
+    #[test]
+    fn concurrent_operations() {
+        // the read overlaps both writes, so it may see either of them
+        let history = vec![
+            ts(1, KVOperation::WriteInvokation, Some(1), 0),
+            ts(3, KVOperation::ReadInvokation, None, 1),
+            ts(2, KVOperation::WriteInvokation, Some(2), 2),
+            ts(1, KVOperation::WriteResponse, Some(1), 3),
+            ts(3, KVOperation::ReadResponse, Some(1), 4),
+            ts(2, KVOperation::WriteResponse, Some(2), 5),
+            ts(4, KVOperation::CasInvokation(1), Some(4), 6),
+            ts(4, KVOperation::CasResponse(false), Some(4), 7),
+        ];
+        assert_eq!(check_history(&history), Ok(()));
+    }
+
+    #[test]
+    fn pending_operations() {
+        // a write that never returned may take effect at any point after it was called
+        let history = vec![
+            ts(1, KVOperation::WriteInvokation, Some(1), 0),
+            ts(2, KVOperation::ReadInvokation, None, 1),
+            ts(2, KVOperation::ReadResponse, None, 2),
+            ts(3, KVOperation::ReadInvokation, None, 3),
+            ts(3, KVOperation::ReadResponse, Some(1), 4),
+            ts(4, KVOperation::ReadInvokation, None, 5),
+        ];
+        assert_eq!(check_history(&history), Ok(()));
+        // but a failed cas can't have swapped
+        let history = vec![
+            ts(1, KVOperation::WriteInvokation, Some(1), 0),
+            ts(1, KVOperation::WriteResponse, Some(1), 1),
+            ts(2, KVOperation::CasInvokation(1), Some(2), 2),
+            ts(2, KVOperation::CasResponse(false), Some(2), 3),
+            ts(3, KVOperation::ReadInvokation, None, 4),
+            ts(3, KVOperation::ReadResponse, Some(2), 5),
+        ];
+        assert_eq!(check_history(&history), Err(1));
+    }
+}
@@ -15,7 +15,7 @@ pub mod raft {
     pub struct RawRaftSer;
 
     #[derive(Debug)]
-    pub struct RaftMsg(pub TikvRaftMsg); // wrapper to implement eager serialisation
+    pub struct RaftMsg(pub u64, pub TikvRaftMsg); // (group, msg), wrapper to implement eager serialisation
 
     impl Serialisable for RaftMsg {
         fn ser_id(&self) -> u64 {
@@ -23,13 +23,15 @@ pub mod raft {
         }
 
         fn size_hint(&self) -> Option<usize> {
-            let num_entries = self.0.entries.len();
-            Some(60 + num_entries * DATA_SIZE_HINT) // TODO
+            let num_entries = self.1.entries.len();
+            let snapshot_size = self.1.get_snapshot().get_data().len();
+            Some(68 + num_entries * DATA_SIZE_HINT + snapshot_size) // TODO
         }
 
         fn serialise(&self, buf: &mut dyn BufMut) -> Result<(), SerError> {
+            buf.put_u64(self.0);
             let bytes = self
-                .0
+                .1
                 .write_to_bytes()
                 .expect("Protobuf failed to serialise TikvRaftMsg");
             buf.put_slice(&bytes);
@@ -41,10 +43,11 @@ pub mod raft {
         }
     }
 
-    impl Deserialiser<TikvRaftMsg> for RawRaftSer {
+    impl Deserialiser<RaftMsg> for RawRaftSer {
         const SER_ID: u64 = serialiser_ids::RAFT_ID;
 
-        fn deserialise(buf: &mut dyn Buf) -> Result<TikvRaftMsg, SerError> {
+        fn deserialise(buf: &mut dyn Buf) -> Result<RaftMsg, SerError> {
+            let group = buf.get_u64();
             let bytes = buf.chunk();
             let remaining = buf.remaining();
             let rm: TikvRaftMsg = if bytes.len() < remaining {
@@ -56,7 +59,543 @@ pub mod raft {
                 parse_from_bytes::<TikvRaftMsg>(bytes)
                     .expect("Protobuf failed to deserialise TikvRaftMsg")
             };
-            Ok(rm)
+            Ok(RaftMsg(group, rm))
+        }
+    }
+}
+
+pub mod paxos {
+    use super::*;
+    use kompact::prelude::{Buf, BufMut, Deserialiser, SerError};
+    use leaderpaxos::{
+        leader_election::{Leader, Round},
+        messages::{
+            AcceptDecide, AcceptSync, Accepted, Decide, FirstAccept, Message, PaxosMsg, Prepare,
+            Promise,
+        },
+        storage::{Entry, StopSign},
+    };
+
+    // ordered by round first, the pid breaks ties
+    #[derive(Clone, Copy, Debug, Default, PartialEq, Eq, PartialOrd, Ord)]
+    pub struct Ballot {
+        pub n: u32,
+        pub pid: u64,
+    }
+
+    impl Ballot {
+        pub fn with(n: u32, pid: u64) -> Ballot {
+            Ballot { n, pid }
+        }
+    }
+
+    impl Round for Ballot {}
+
+    #[derive(Debug)]
+    pub struct PaxosMsgWrapper(pub Message<Ballot>); // wrapper to implement eager serialisation
+
+    pub struct PaxosSer;
+
+    const PREPAREREQ_ID: u8 = 1;
+    const PREPARE_ID: u8 = 2;
+    const PROMISE_ID: u8 = 3;
+    const ACCEPTSYNC_ID: u8 = 4;
+    const FIRSTACCEPT_ID: u8 = 5;
+    const ACCEPTDECIDE_ID: u8 = 6;
+    const ACCEPTED_ID: u8 = 7;
+    const DECIDE_ID: u8 = 8;
+    const PROPOSALFORWARD_ID: u8 = 9;
+
+    const NORMAL_ENTRY_ID: u8 = 1;
+    const STOPSIGN_ENTRY_ID: u8 = 2;
+
+    const BALLOT_SIZE: usize = 12;
+
+    fn serialise_ballot(ballot: &Ballot, buf: &mut dyn BufMut) {
+        buf.put_u32(ballot.n);
+        buf.put_u64(ballot.pid);
+    }
+
+    fn deserialise_ballot(buf: &mut dyn Buf) -> Ballot {
+        let n = buf.get_u32();
+        let pid = buf.get_u64();
+        Ballot::with(n, pid)
+    }
+
+    fn serialise_leader(leader: &Option<Leader<Ballot>>, buf: &mut dyn BufMut) {
+        match leader {
+            Some(l) => {
+                buf.put_u8(1);
+                buf.put_u64(l.pid);
+                serialise_ballot(&l.round, buf);
+            }
+            None => buf.put_u8(0),
+        }
+    }
+
+    fn deserialise_leader(buf: &mut dyn Buf) -> Option<Leader<Ballot>> {
+        match buf.get_u8() {
+            0 => None,
+            _ => {
+                let pid = buf.get_u64();
+                Some(Leader::with(pid, deserialise_ballot(buf)))
+            }
+        }
+    }
+
+    fn entry_size(entry: &Entry<Ballot>) -> usize {
+        match entry {
+            Entry::Normal(data) => 5 + data.len(),
+            Entry::StopSign(ss) => 10 + 8 * ss.nodes.len() + 9 + BALLOT_SIZE,
+        }
+    }
+
+    pub(crate) fn entries_size(entries: &[Entry<Ballot>]) -> usize {
+        4 + entries.iter().map(entry_size).sum::<usize>()
+    }
+
+    pub(crate) fn serialise_entries(entries: &[Entry<Ballot>], buf: &mut dyn BufMut) {
+        buf.put_u32(entries.len() as u32);
+        for entry in entries {
+            match entry {
+                Entry::Normal(data) => {
+                    buf.put_u8(NORMAL_ENTRY_ID);
+                    buf.put_u32(data.len() as u32);
+                    buf.put_slice(data);
+                }
+                Entry::StopSign(ss) => {
+                    buf.put_u8(STOPSIGN_ENTRY_ID);
+                    buf.put_u32(ss.config_id);
+                    buf.put_u32(ss.nodes.len() as u32);
+                    for pid in &ss.nodes {
+                        buf.put_u64(*pid);
+                    }
+                    serialise_leader(&ss.skip_prepare_use_leader, buf);
+                }
+            }
+        }
+    }
+
+    pub(crate) fn deserialise_entries(buf: &mut dyn Buf) -> Result<Vec<Entry<Ballot>>, SerError> {
+        let len = buf.get_u32() as usize;
+        let mut entries = Vec::with_capacity(len);
+        for _ in 0..len {
+            let entry = match buf.get_u8() {
+                NORMAL_ENTRY_ID => {
+                    let data_len = buf.get_u32() as usize;
+                    let mut data = vec![0; data_len];
+                    buf.copy_to_slice(&mut data);
+                    Entry::Normal(data)
+                }
+                STOPSIGN_ENTRY_ID => {
+                    let config_id = buf.get_u32();
+                    let nodes_len = buf.get_u32() as usize;
+                    let mut nodes = Vec::with_capacity(nodes_len);
+                    for _ in 0..nodes_len {
+                        nodes.push(buf.get_u64());
+                    }
+                    let skip_prepare_use_leader = deserialise_leader(buf);
+                    Entry::StopSign(StopSign::with(config_id, nodes, skip_prepare_use_leader))
+                }
+                _ => {
+                    return Err(SerError::InvalidType(
+                        "Found unkown id but expected Normal or StopSign entry".into(),
+                    ))
+                }
+            };
+            entries.push(entry);
+        }
+        Ok(entries)
+    }
+
+    impl Serialisable for PaxosMsgWrapper {
+        fn ser_id(&self) -> u64 {
+            serialiser_ids::PAXOS_ID
+        }
+
+        fn size_hint(&self) -> Option<usize> {
+            let msg_size = match &self.0.msg {
+                PaxosMsg::PrepareReq => 0,
+                PaxosMsg::Prepare(_) => 2 * BALLOT_SIZE + 16,
+                PaxosMsg::Promise(p) => 2 * BALLOT_SIZE + 8 + entries_size(&p.sfx),
+                PaxosMsg::AcceptSync(acc_sync) => BALLOT_SIZE + 9 + entries_size(&acc_sync.entries),
+                PaxosMsg::FirstAccept(f) => BALLOT_SIZE + entries_size(&f.entries),
+                PaxosMsg::AcceptDecide(a) => BALLOT_SIZE + 8 + entries_size(&a.entries),
+                PaxosMsg::Accepted(_) => BALLOT_SIZE + 8,
+                PaxosMsg::Decide(_) => BALLOT_SIZE + 8,
+                PaxosMsg::ProposalForward(entries) => entries_size(entries),
+            };
+            Some(17 + msg_size)
+        }
+
+        fn serialise(&self, buf: &mut dyn BufMut) -> Result<(), SerError> {
+            buf.put_u64(self.0.from);
+            buf.put_u64(self.0.to);
+            match &self.0.msg {
+                PaxosMsg::PrepareReq => buf.put_u8(PREPAREREQ_ID),
+                PaxosMsg::Prepare(p) => {
+                    buf.put_u8(PREPARE_ID);
+                    serialise_ballot(&p.n, buf);
+                    buf.put_u64(p.ld);
+                    serialise_ballot(&p.n_accepted, buf);
+                    buf.put_u64(p.sfx_len);
+                }
+                PaxosMsg::Promise(p) => {
+                    buf.put_u8(PROMISE_ID);
+                    serialise_ballot(&p.n, buf);
+                    serialise_ballot(&p.n_accepted, buf);
+                    buf.put_u64(p.ld);
+                    serialise_entries(&p.sfx, buf);
+                }
+                PaxosMsg::AcceptSync(acc_sync) => {
+                    buf.put_u8(ACCEPTSYNC_ID);
+                    serialise_ballot(&acc_sync.n, buf);
+                    buf.put_u64(acc_sync.ld);
+                    buf.put_u8(acc_sync.sync as u8);
+                    serialise_entries(&acc_sync.entries, buf);
+                }
+                PaxosMsg::FirstAccept(f) => {
+                    buf.put_u8(FIRSTACCEPT_ID);
+                    serialise_ballot(&f.n, buf);
+                    serialise_entries(&f.entries, buf);
+                }
+                PaxosMsg::AcceptDecide(a) => {
+                    buf.put_u8(ACCEPTDECIDE_ID);
+                    serialise_ballot(&a.n, buf);
+                    buf.put_u64(a.ld);
+                    serialise_entries(&a.entries, buf);
+                }
+                PaxosMsg::Accepted(acc) => {
+                    buf.put_u8(ACCEPTED_ID);
+                    serialise_ballot(&acc.n, buf);
+                    buf.put_u64(acc.la);
+                }
+                PaxosMsg::Decide(d) => {
+                    buf.put_u8(DECIDE_ID);
+                    serialise_ballot(&d.n, buf);
+                    buf.put_u64(d.ld);
+                }
+                PaxosMsg::ProposalForward(entries) => {
+                    buf.put_u8(PROPOSALFORWARD_ID);
+                    serialise_entries(entries, buf);
+                }
+            }
+            Ok(())
+        }
+
+        fn local(self: Box<Self>) -> Result<Box<dyn Any + Send>, Box<dyn Serialisable>> {
+            Ok(self)
+        }
+    }
+
+    impl Deserialiser<Message<Ballot>> for PaxosSer {
+        const SER_ID: u64 = serialiser_ids::PAXOS_ID;
+
+        fn deserialise(buf: &mut dyn Buf) -> Result<Message<Ballot>, SerError> {
+            let from = buf.get_u64();
+            let to = buf.get_u64();
+            let msg = match buf.get_u8() {
+                PREPAREREQ_ID => PaxosMsg::PrepareReq,
+                PREPARE_ID => {
+                    let n = deserialise_ballot(buf);
+                    let ld = buf.get_u64();
+                    let n_accepted = deserialise_ballot(buf);
+                    let sfx_len = buf.get_u64();
+                    PaxosMsg::Prepare(Prepare::with(n, ld, n_accepted, sfx_len))
+                }
+                PROMISE_ID => {
+                    let n = deserialise_ballot(buf);
+                    let n_accepted = deserialise_ballot(buf);
+                    let ld = buf.get_u64();
+                    let sfx = deserialise_entries(buf)?;
+                    PaxosMsg::Promise(Promise::with(n, n_accepted, sfx, ld))
+                }
+                ACCEPTSYNC_ID => {
+                    let n = deserialise_ballot(buf);
+                    let ld = buf.get_u64();
+                    let sync = buf.get_u8() != 0;
+                    let sfx = deserialise_entries(buf)?;
+                    PaxosMsg::AcceptSync(AcceptSync::with(n, sfx, ld, sync))
+                }
+                FIRSTACCEPT_ID => {
+                    let n = deserialise_ballot(buf);
+                    let entries = deserialise_entries(buf)?;
+                    PaxosMsg::FirstAccept(FirstAccept::with(n, entries))
+                }
+                ACCEPTDECIDE_ID => {
+                    let n = deserialise_ballot(buf);
+                    let ld = buf.get_u64();
+                    let entries = deserialise_entries(buf)?;
+                    PaxosMsg::AcceptDecide(AcceptDecide::with(n, ld, entries))
+                }
+                ACCEPTED_ID => {
+                    let n = deserialise_ballot(buf);
+                    let la = buf.get_u64();
+                    PaxosMsg::Accepted(Accepted::with(n, la))
+                }
+                DECIDE_ID => {
+                    let n = deserialise_ballot(buf);
+                    let ld = buf.get_u64();
+                    PaxosMsg::Decide(Decide::with(ld, n))
+                }
+                PROPOSALFORWARD_ID => PaxosMsg::ProposalForward(deserialise_entries(buf)?),
+                _ => {
+                    return Err(SerError::InvalidType(
+                        "Found unkown id but expected PaxosMsg".into(),
+                    ))
+                }
+            };
+            Ok(Message::with(from, to, msg))
+        }
+    }
+
+    #[derive(Clone, Debug)]
+    pub enum HeartbeatMsg {
+        Request(u32),             // round
+        Reply(u32, Ballot, bool), // (round, ballot, majority connected)
+    }
+
+    #[derive(Clone, Debug)]
+    pub struct BLEMessage {
+        pub from: u64,
+        pub to: u64,
+        pub msg: HeartbeatMsg,
+    }
+
+    impl BLEMessage {
+        pub fn with(from: u64, to: u64, msg: HeartbeatMsg) -> BLEMessage {
+            BLEMessage { from, to, msg }
+        }
+    }
+
+    const HB_REQUEST_ID: u8 = 1;
+    const HB_REPLY_ID: u8 = 2;
+
+    pub struct BLESer;
+
+    impl Serialisable for BLEMessage {
+        fn ser_id(&self) -> u64 {
+            serialiser_ids::BLE_ID
+        }
+
+        fn size_hint(&self) -> Option<usize> {
+            Some(17 + 4 + BALLOT_SIZE + 1)
+        }
+
+        fn serialise(&self, buf: &mut dyn BufMut) -> Result<(), SerError> {
+            buf.put_u64(self.from);
+            buf.put_u64(self.to);
+            match &self.msg {
+                HeartbeatMsg::Request(round) => {
+                    buf.put_u8(HB_REQUEST_ID);
+                    buf.put_u32(*round);
+                }
+                HeartbeatMsg::Reply(round, ballot, majority_connected) => {
+                    buf.put_u8(HB_REPLY_ID);
+                    buf.put_u32(*round);
+                    serialise_ballot(ballot, buf);
+                    buf.put_u8(*majority_connected as u8);
+                }
+            }
+            Ok(())
+        }
+
+        fn local(self: Box<Self>) -> Result<Box<dyn Any + Send>, Box<dyn Serialisable>> {
+            Ok(self)
+        }
+    }
+
+    impl Deserialiser<BLEMessage> for BLESer {
+        const SER_ID: u64 = serialiser_ids::BLE_ID;
+
+        fn deserialise(buf: &mut dyn Buf) -> Result<BLEMessage, SerError> {
+            let from = buf.get_u64();
+            let to = buf.get_u64();
+            let msg = match buf.get_u8() {
+                HB_REQUEST_ID => HeartbeatMsg::Request(buf.get_u32()),
+                HB_REPLY_ID => {
+                    let round = buf.get_u32();
+                    let ballot = deserialise_ballot(buf);
+                    let majority_connected = buf.get_u8() != 0;
+                    HeartbeatMsg::Reply(round, ballot, majority_connected)
+                }
+                _ => {
+                    return Err(SerError::InvalidType(
+                        "Found unkown id but expected HeartbeatRequest or HeartbeatReply".into(),
+                    ))
+                }
+            };
+            Ok(BLEMessage::with(from, to, msg))
+        }
+    }
+
+    // sent by the leader of a stopped configuration to the nodes of the next one
+    #[derive(Clone, Debug)]
+    pub struct ReconfigInit {
+        pub config_id: u32,
+        pub nodes: Vec<u64>,
+        pub continued_nodes: Vec<u64>, // nodes of the previous configuration that hold its sequence
+        pub skip_prepare_use_leader: Option<Leader<Ballot>>,
+        pub seq_len: u64, // length of the sequence decided in the previous configurations
+        pub sequence: Vec<Entry<Ballot>>, // the whole sequence with the eager policy, empty with pull
+        pub from: u64,
+    }
+
+    #[derive(Clone, Debug)]
+    pub struct SequenceRequest {
+        pub config_id: u32,
+        pub from_idx: u64,
+        pub to_idx: u64,
+        pub requestor: u64,
+    }
+
+    #[derive(Clone, Debug)]
+    pub struct SequenceSegment {
+        pub config_id: u32,
+        pub from_idx: u64,
+        pub entries: Vec<Entry<Ballot>>,
+    }
+
+    #[derive(Clone, Debug)]
+    pub enum ReconfigurationMsg {
+        Init(ReconfigInit),
+        Ready(u32, u64), // (config_id, pid) the node has started its replica of the configuration
+        SequenceRequest(SequenceRequest),
+        SequenceSegment(SequenceSegment),
+    }
+
+    const INIT_ID: u8 = 1;
+    const READY_ID: u8 = 2;
+    const SEQ_REQUEST_ID: u8 = 3;
+    const SEQ_SEGMENT_ID: u8 = 4;
+
+    pub struct ReconfigSer;
+
+    impl Serialisable for ReconfigurationMsg {
+        fn ser_id(&self) -> u64 {
+            serialiser_ids::RECONFIG_ID
+        }
+
+        fn size_hint(&self) -> Option<usize> {
+            let msg_size = match self {
+                ReconfigurationMsg::Init(r) => {
+                    let nodes_size = 8 * (r.nodes.len() + r.continued_nodes.len());
+                    12 + nodes_size + 9 + BALLOT_SIZE + 16 + entries_size(&r.sequence)
+                }
+                ReconfigurationMsg::Ready(_, _) => 12,
+                ReconfigurationMsg::SequenceRequest(_) => 28,
+                ReconfigurationMsg::SequenceSegment(s) => 12 + entries_size(&s.entries),
+            };
+            Some(1 + msg_size)
+        }
+
+        fn serialise(&self, buf: &mut dyn BufMut) -> Result<(), SerError> {
+            match self {
+                ReconfigurationMsg::Init(r) => {
+                    buf.put_u8(INIT_ID);
+                    buf.put_u32(r.config_id);
+                    buf.put_u32(r.nodes.len() as u32);
+                    for pid in &r.nodes {
+                        buf.put_u64(*pid);
+                    }
+                    buf.put_u32(r.continued_nodes.len() as u32);
+                    for pid in &r.continued_nodes {
+                        buf.put_u64(*pid);
+                    }
+                    serialise_leader(&r.skip_prepare_use_leader, buf);
+                    buf.put_u64(r.seq_len);
+                    buf.put_u64(r.from);
+                    serialise_entries(&r.sequence, buf);
+                }
+                ReconfigurationMsg::Ready(config_id, pid) => {
+                    buf.put_u8(READY_ID);
+                    buf.put_u32(*config_id);
+                    buf.put_u64(*pid);
+                }
+                ReconfigurationMsg::SequenceRequest(sr) => {
+                    buf.put_u8(SEQ_REQUEST_ID);
+                    buf.put_u32(sr.config_id);
+                    buf.put_u64(sr.from_idx);
+                    buf.put_u64(sr.to_idx);
+                    buf.put_u64(sr.requestor);
+                }
+                ReconfigurationMsg::SequenceSegment(s) => {
+                    buf.put_u8(SEQ_SEGMENT_ID);
+                    buf.put_u32(s.config_id);
+                    buf.put_u64(s.from_idx);
+                    serialise_entries(&s.entries, buf);
+                }
+            }
+            Ok(())
+        }
+
+        fn local(self: Box<Self>) -> Result<Box<dyn Any + Send>, Box<dyn Serialisable>> {
+            Ok(self)
+        }
+    }
+
+    impl Deserialiser<ReconfigurationMsg> for ReconfigSer {
+        const SER_ID: u64 = serialiser_ids::RECONFIG_ID;
+
+        fn deserialise(buf: &mut dyn Buf) -> Result<ReconfigurationMsg, SerError> {
+            match buf.get_u8() {
+                INIT_ID => {
+                    let config_id = buf.get_u32();
+                    let nodes_len = buf.get_u32() as usize;
+                    let mut nodes = Vec::with_capacity(nodes_len);
+                    for _ in 0..nodes_len {
+                        nodes.push(buf.get_u64());
+                    }
+                    let continued_len = buf.get_u32() as usize;
+                    let mut continued_nodes = Vec::with_capacity(continued_len);
+                    for _ in 0..continued_len {
+                        continued_nodes.push(buf.get_u64());
+                    }
+                    let skip_prepare_use_leader = deserialise_leader(buf);
+                    let seq_len = buf.get_u64();
+                    let from = buf.get_u64();
+                    let sequence = deserialise_entries(buf)?;
+                    Ok(ReconfigurationMsg::Init(ReconfigInit {
+                        config_id,
+                        nodes,
+                        continued_nodes,
+                        skip_prepare_use_leader,
+                        seq_len,
+                        sequence,
+                        from,
+                    }))
+                }
+                READY_ID => {
+                    let config_id = buf.get_u32();
+                    let pid = buf.get_u64();
+                    Ok(ReconfigurationMsg::Ready(config_id, pid))
+                }
+                SEQ_REQUEST_ID => {
+                    let config_id = buf.get_u32();
+                    let from_idx = buf.get_u64();
+                    let to_idx = buf.get_u64();
+                    let requestor = buf.get_u64();
+                    Ok(ReconfigurationMsg::SequenceRequest(SequenceRequest {
+                        config_id,
+                        from_idx,
+                        to_idx,
+                        requestor,
+                    }))
+                }
+                SEQ_SEGMENT_ID => {
+                    let config_id = buf.get_u32();
+                    let from_idx = buf.get_u64();
+                    let entries = deserialise_entries(buf)?;
+                    Ok(ReconfigurationMsg::SequenceSegment(SequenceSegment {
+                        config_id,
+                        from_idx,
+                        entries,
+                    }))
+                }
+                _ => Err(SerError::InvalidType(
+                    "Found unkown id but expected ReconfigurationMsg".into(),
+                )),
+            }
         }
     }
 }
@@ -67,6 +606,11 @@ pub struct Run;
 
 pub const RECONFIG_ID: u64 = 0;
 
+// proposals are partitioned across the Raft groups by key, reconfigurations belong to the first group
+pub fn group_of(key: u64, num_groups: u64) -> u64 {
+    key.saturating_sub(1) % num_groups
+}
+
 #[derive(Clone, Debug)]
 pub struct Proposal {
     pub data: Vec<u8>,
@@ -104,18 +648,64 @@ impl ProposalResp {
     }
 }
 
+#[derive(Clone, Debug)]
+pub struct ReadResp {
+    pub id: u64,
+    pub index: u64, // the read index the read was served at
+    pub latest_leader: u64,
+}
+
+impl ReadResp {
+    pub fn with(id: u64, index: u64, latest_leader: u64) -> ReadResp {
+        ReadResp {
+            id,
+            index,
+            latest_leader,
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub enum RejectReason {
+    NotLeader(u64), // hint of the current leader, 0 if unknown
+    Dropped,
+}
+
+#[derive(Clone, Debug)]
+pub struct ProposalRejected {
+    pub id: u64,
+    pub reason: RejectReason,
+}
+
+impl ProposalRejected {
+    pub fn with(id: u64, reason: RejectReason) -> ProposalRejected {
+        ProposalRejected { id, reason }
+    }
+}
+
 #[derive(Clone, Debug)]
 pub enum AtomicBroadcastMsg {
     Proposal(Proposal),
     ProposalResp(ProposalResp),
     FirstLeader(u64),
     PendingReconfiguration(Vec<u8>),
+    ReadRequest(u64),
+    ReadResp(ReadResp),
+    TransferLeader(u64),
+    ProposalRejected(ProposalRejected),
 }
 
 const PROPOSAL_ID: u8 = 1;
 const PROPOSALRESP_ID: u8 = 2;
 const FIRSTLEADER_ID: u8 = 3;
 const PENDINGRECONFIG_ID: u8 = 4;
+const READREQUEST_ID: u8 = 5;
+const READRESP_ID: u8 = 6;
+const TRANSFERLEADER_ID: u8 = 7;
+const PROPOSALREJECTED_ID: u8 = 8;
+
+const NOTLEADER_ID: u8 = 1;
+const DROPPED_ID: u8 = 2;
 
 impl Serialisable for AtomicBroadcastMsg {
     fn ser_id(&self) -> u64 {
@@ -134,6 +724,10 @@ impl Serialisable for AtomicBroadcastMsg {
             AtomicBroadcastMsg::ProposalResp(_) => 13 + DATA_SIZE_HINT,
             AtomicBroadcastMsg::PendingReconfiguration(_) => 5 + DATA_SIZE_HINT,
             AtomicBroadcastMsg::FirstLeader(_) => 9,
+            AtomicBroadcastMsg::ReadRequest(_) => 9,
+            AtomicBroadcastMsg::ReadResp(_) => 25,
+            AtomicBroadcastMsg::TransferLeader(_) => 9,
+            AtomicBroadcastMsg::ProposalRejected(_) => 18,
         };
         Some(msg_size)
     }
@@ -183,6 +777,31 @@ impl Serialisable for AtomicBroadcastMsg {
                 buf.put_u32(d.len() as u32);
                 buf.put_slice(d);
             }
+            AtomicBroadcastMsg::ReadRequest(id) => {
+                buf.put_u8(READREQUEST_ID);
+                buf.put_u64(*id);
+            }
+            AtomicBroadcastMsg::ReadResp(rr) => {
+                buf.put_u8(READRESP_ID);
+                buf.put_u64(rr.id);
+                buf.put_u64(rr.index);
+                buf.put_u64(rr.latest_leader);
+            }
+            AtomicBroadcastMsg::TransferLeader(pid) => {
+                buf.put_u8(TRANSFERLEADER_ID);
+                buf.put_u64(*pid);
+            }
+            AtomicBroadcastMsg::ProposalRejected(pr) => {
+                buf.put_u8(PROPOSALREJECTED_ID);
+                buf.put_u64(pr.id);
+                match pr.reason {
+                    RejectReason::NotLeader(hint) => {
+                        buf.put_u8(NOTLEADER_ID);
+                        buf.put_u64(hint);
+                    }
+                    RejectReason::Dropped => buf.put_u8(DROPPED_ID),
+                }
+            }
         }
         Ok(())
     }
@@ -245,6 +864,39 @@ impl Deserialiser<AtomicBroadcastMsg> for AtomicBroadcastDeser {
                 buf.copy_to_slice(&mut data);
                 Ok(AtomicBroadcastMsg::PendingReconfiguration(data))
             }
+            READREQUEST_ID => {
+                let id = buf.get_u64();
+                Ok(AtomicBroadcastMsg::ReadRequest(id))
+            }
+            READRESP_ID => {
+                let id = buf.get_u64();
+                let index = buf.get_u64();
+                let latest_leader = buf.get_u64();
+                Ok(AtomicBroadcastMsg::ReadResp(ReadResp::with(
+                    id,
+                    index,
+                    latest_leader,
+                )))
+            }
+            TRANSFERLEADER_ID => {
+                let pid = buf.get_u64();
+                Ok(AtomicBroadcastMsg::TransferLeader(pid))
+            }
+            PROPOSALREJECTED_ID => {
+                let id = buf.get_u64();
+                let reason = match buf.get_u8() {
+                    NOTLEADER_ID => RejectReason::NotLeader(buf.get_u64()),
+                    DROPPED_ID => RejectReason::Dropped,
+                    _ => {
+                        return Err(SerError::InvalidType(
+                            "Found unkown id but expected NotLeader or Dropped".into(),
+                        ))
+                    }
+                };
+                Ok(AtomicBroadcastMsg::ProposalRejected(
+                    ProposalRejected::with(id, reason),
+                ))
+            }
             _ => Err(SerError::InvalidType(
                 "Found unkown id but expected RaftMsg, Proposal or ProposalResp".into(),
             )),
@@ -1,10 +1,14 @@
 pub mod atomic_broadcast;
 mod client;
 mod communicator;
+mod kv;
+mod linearizability;
 mod messages;
+pub(crate) mod paxos;
 pub(crate) mod raft;
 mod storage;
 mod partitioning_actor;
+mod replicated_log;
 mod serialiser_ids;
 pub(crate) mod kompact_system_provider;
 mod atomic_broadcast_request;
\ No newline at end of file
@@ -9,6 +9,8 @@ use synchronoise::CountdownEvent;
         ReadResponse,
         WriteInvokation,
         WriteResponse,
+        CasInvokation(u32), // expected value, the value of the timestamp is the new one
+        CasResponse(bool),  // whether the swap was applied
     }
 
     #[derive(Clone, Copy, Debug)]
@@ -35,6 +37,8 @@ pub struct PartitioningActor {
     init_ack_count: u32,
     done_count: u32,
     test_promise: Option<KPromise<Vec<KVTimestamp>>>,
+    num_testers: u32, // number of TestDone messages the test results are collected from
+    test_done_count: u32,
     test_results: Vec<KVTimestamp>,
 }
 
@@ -45,6 +49,7 @@ impl PartitioningActor {
         init_id: u32,
         nodes: Vec<ActorPath>,
         test_promise: Option<KPromise<Vec<KVTimestamp>>>,
+        num_testers: u32,
     ) -> PartitioningActor {
         PartitioningActor {
             ctx: ComponentContext::uninitialised(),
@@ -58,6 +63,8 @@ impl PartitioningActor {
             init_ack_count: 0,
             done_count: 0,
             test_promise,
+            num_testers,
+            test_done_count: 0,
             test_results: Vec::new(),
         }
     }
@@ -126,9 +133,9 @@ impl Actor for PartitioningActor {
                         }
                     },
                     PartitioningActorMsg::TestDone(td) => {
-                        self.done_count += 1;
+                        self.test_done_count += 1;
                         self.test_results.extend(td);
-                        if self.done_count == self.n {
+                        if self.test_done_count == self.num_testers {
                             self.test_promise
                                 .take()
                                 .unwrap()
@@ -196,6 +203,9 @@ const READ_INV: u8 = 8;
 const READ_RESP: u8 = 9;
 const WRITE_INV: u8 = 10;
 const WRITE_RESP: u8 = 11;
+const READ_RESP_NONE: u8 = 12;
+const CAS_INV: u8 = 14;
+const CAS_RESP: u8 = 15;
 
 impl Serialisable for PartitioningActorMsg {
     fn ser_id(&self) -> u64 {
@@ -210,7 +220,7 @@ impl Serialisable for PartitioningActorMsg {
             PartitioningActorMsg::Done => Some(1),
             PartitioningActorMsg::Stop => Some(1),
             PartitioningActorMsg::StopAck => Some(1),
-            PartitioningActorMsg::TestDone(_) => Some(100000),
+            PartitioningActorMsg::TestDone(timestamps) => Some(5 + 34 * timestamps.len()),
         }
     }
 
@@ -247,10 +257,13 @@ impl Serialisable for PartitioningActorMsg {
                     buf.put_u64(ts.key);
                     match ts.operation {
                         KVOperation::ReadInvokation => buf.put_u8(READ_INV),
-                        KVOperation::ReadResponse => {
-                            buf.put_u8(READ_RESP);
-                            buf.put_u32(ts.value.unwrap());
-                        }
+                        KVOperation::ReadResponse => match ts.value {
+                            Some(value) => {
+                                buf.put_u8(READ_RESP);
+                                buf.put_u32(value);
+                            }
+                            None => buf.put_u8(READ_RESP_NONE),
+                        },
                         KVOperation::WriteInvokation => {
                             buf.put_u8(WRITE_INV);
                             buf.put_u32(ts.value.unwrap());
@@ -259,6 +272,16 @@ impl Serialisable for PartitioningActorMsg {
                             buf.put_u8(WRITE_RESP);
                             buf.put_u32(ts.value.unwrap());
                         }
+                        KVOperation::CasInvokation(expected) => {
+                            buf.put_u8(CAS_INV);
+                            buf.put_u32(expected);
+                            buf.put_u32(ts.value.unwrap());
+                        }
+                        KVOperation::CasResponse(swapped) => {
+                            buf.put_u8(CAS_RESP);
+                            buf.put_u8(swapped as u8);
+                            buf.put_u32(ts.value.unwrap());
+                        }
                     }
                     buf.put_i64(ts.time);
                     buf.put_u32(ts.sender);
@@ -322,8 +345,17 @@ impl Deserialiser<PartitioningActorMsg> for PartitioningActorSer {
                     let (operation, value) = match buf.get_u8() {
                         READ_INV => (KVOperation::ReadInvokation, None),
                         READ_RESP => (KVOperation::ReadResponse, Some(buf.get_u32())),
+                        READ_RESP_NONE => (KVOperation::ReadResponse, None),
                         WRITE_INV => (KVOperation::WriteInvokation, Some(buf.get_u32())),
                         WRITE_RESP => (KVOperation::WriteResponse, Some(buf.get_u32())),
+                        CAS_INV => (
+                            KVOperation::CasInvokation(buf.get_u32()),
+                            Some(buf.get_u32()),
+                        ),
+                        CAS_RESP => (
+                            KVOperation::CasResponse(buf.get_u8() != 0),
+                            Some(buf.get_u32()),
+                        ),
                         _ => panic!("Found unknown KVOperation id"),
                     };
                     let time = buf.get_i64();
@@ -0,0 +1,1341 @@
+use super::{
+    kv::{deserialise_commands, KVCommand, KVStore},
+    messages::{
+        paxos::{
+            BLEMessage, Ballot, HeartbeatMsg, ReconfigInit, ReconfigSer, ReconfigurationMsg,
+            SequenceRequest, SequenceSegment,
+        },
+        StopMsg as NetStopMsg, StopMsgDeser, *,
+    },
+    storage::paxos::{MemorySequence, MemoryState},
+};
+use crate::atomic_broadcast::{
+    atomic_broadcast::{Done, SequenceResp, PAXOS_PATH},
+    communicator::{AtomicBroadcastCompMsg, CommunicationPort, Communicator, CommunicatorMsg},
+    partitioning_actor::{Init, PartitioningActorMsg, PartitioningActorSer},
+    replicated_log::ReplicatedLog,
+    serialiser_ids::ATOMICBCAST_ID,
+};
+use hashbrown::{HashMap, HashSet};
+use kompact::prelude::*;
+use leaderpaxos::{
+    leader_election::Leader,
+    messages::{Message as RawPaxosMsg, PaxosMsg},
+    paxos::{Paxos, ProposeErr},
+    storage::{Entry, PaxosState, Sequence, StopSign, Storage},
+};
+use std::{borrow::Borrow, ops::DerefMut, sync::Arc, time::Duration};
+
+const COMMUNICATOR: &str = "communicator";
+const DELAY: Duration = Duration::from_millis(0);
+
+#[derive(Clone, Debug)]
+pub enum ReconfigurationPolicy {
+    Eager, // the leader of the stopped configuration sends its whole sequence to the new nodes
+    Pull,  // the new nodes request segments of the sequence from the continued nodes
+}
+
+#[derive(Debug)]
+pub enum PaxosCompMsg {
+    Leader(u32, u64), // (config_id, pid)
+    ForwardReconfig(u64, (Vec<u64>, Vec<u64>)),
+    Reconfig(u32, StopSign<Ballot>, Vec<Entry<Ballot>>, bool), // (config_id, stopsign, decided sequence, was leader)
+    KillComponents(Ask<(), Done>),
+    GetSequence(Ask<(), SequenceResp>),
+}
+
+struct ConfigReplica {
+    config_id: u32,
+    nodes: Vec<u64>,
+    replica: Arc<Component<PaxosReplica>>,
+    communicator: Arc<Component<Communicator>>,
+}
+
+struct SequenceTransfer {
+    config_id: u32,
+    sources: Vec<u64>,
+    segments: Vec<(u64, u64)>, // [from_idx, to_idx) of each requested segment
+    received: HashMap<u64, Vec<Entry<Ballot>>>, // from_idx -> entries
+    attempt: usize,            // rotates the sources on every retry
+    timer: Option<ScheduledTimer>,
+}
+
+#[derive(ComponentDefinition)]
+pub struct PaxosComp {
+    ctx: ComponentContext<Self>,
+    pid: u64,
+    initial_config: Vec<u64>,
+    reconfig_policy: ReconfigurationPolicy,
+    replicas: Vec<ConfigReplica>, // replicas of stopped configurations are kept until killed
+    active_config: u32,           // 0 if this node has no running replica
+    latest_config: (u32, Vec<u64>),
+    peers: HashMap<u64, ActorPath>,
+    iteration_id: u32,
+    stopped: bool,
+    partitioning_actor: Option<ActorPath>,
+    cached_client: Option<ActorPath>,
+    current_leader: u64,
+    prev_sequence: Vec<Entry<Ballot>>, // decided entries of the stopped configurations
+    transfer: Option<SequenceTransfer>,
+    transfer_suffix: Vec<Entry<Ballot>>, // decided locally while the transfer of the prefix is pending
+    held_requests: Vec<SequenceRequest>, // requests for entries this node has not got yet
+    deferred_init: Option<(StopSign<Ballot>, Vec<u64>)>, // sent to the new nodes once the transfer is complete
+    ready_peers: HashMap<u32, HashSet<u64>>, // peers that started their replica before this node did
+}
+
+impl PaxosComp {
+    pub fn with(initial_config: Vec<u64>, reconfig_policy: ReconfigurationPolicy) -> Self {
+        PaxosComp {
+            ctx: ComponentContext::uninitialised(),
+            pid: 0,
+            initial_config,
+            reconfig_policy,
+            replicas: vec![],
+            active_config: 0,
+            latest_config: (0, vec![]),
+            peers: HashMap::new(),
+            iteration_id: 0,
+            stopped: false,
+            partitioning_actor: None,
+            cached_client: None,
+            current_leader: 0,
+            prev_sequence: vec![],
+            transfer: None,
+            transfer_suffix: vec![],
+            held_requests: vec![],
+            deferred_init: None,
+            ready_peers: HashMap::new(),
+        }
+    }
+
+    fn communicator_alias(pid: u64, config_id: u32, iteration_id: u32) -> String {
+        format!("{}{}-{}-{}", COMMUNICATOR, pid, config_id, iteration_id)
+    }
+
+    fn active_replica(&self) -> Option<&ConfigReplica> {
+        self.replicas
+            .iter()
+            .find(|r| r.config_id == self.active_config)
+    }
+
+    fn create_components(
+        &mut self,
+        config_id: u32,
+        nodes: Vec<u64>,
+        skip_prepare_use_leader: Option<Leader<Ballot>>,
+        kv_store: Option<KVStore>,
+    ) -> Handled {
+        let mut communicator_peers: HashMap<u64, ActorPath> = HashMap::with_capacity(nodes.len());
+        for pid in nodes.iter().filter(|pid| **pid != self.pid) {
+            let ap = self
+                .peers
+                .get(pid)
+                .unwrap_or_else(|| panic!("No actorpath for node {}", pid));
+            match ap {
+                ActorPath::Named(n) => {
+                    let sys_path = n.system();
+                    let protocol = sys_path.protocol();
+                    let port = sys_path.port();
+                    let addr = sys_path.address();
+                    let named_communicator = NamedPath::new(
+                        protocol,
+                        *addr,
+                        port,
+                        vec![Self::communicator_alias(*pid, config_id, self.iteration_id)],
+                    );
+                    communicator_peers.insert(*pid, ActorPath::Named(named_communicator));
+                }
+                _ => unimplemented!(),
+            }
+        }
+
+        let system = self.ctx.system();
+        let max_inflight = self.ctx.config()["experiment"]["max_inflight"]
+            .as_i64()
+            .expect("Failed to load max_inflight") as usize;
+        let max_pending_proposals = self.ctx.config()["paxos"]["max_pending_proposals"]
+            .as_i64()
+            .expect("Failed to load max_pending_proposals")
+            as usize;
+        let prio_start_round = self.ctx.config()["paxos"]["prio_start_round"]
+            .as_i64()
+            .expect("Failed to load prio_start_round") as u32;
+        // the first configuration is started by the partitioning actor on all nodes at once
+        let ready_peers = if config_id == 1 {
+            communicator_peers.keys().cloned().collect()
+        } else {
+            self.ready_peers.remove(&config_id).unwrap_or_default()
+        };
+        let peers: Vec<u64> = communicator_peers.keys().cloned().collect();
+        let (paxos_replica, paxos_f) = system.create_and_register(|| {
+            PaxosReplica::with(
+                config_id,
+                self.pid,
+                nodes.clone(),
+                peers,
+                skip_prepare_use_leader,
+                self.actor_ref(),
+                max_inflight,
+                max_pending_proposals,
+                prio_start_round,
+                ready_peers,
+                kv_store,
+            )
+        });
+        let (communicator, comm_f) = system.create_and_register(|| {
+            Communicator::with(
+                communicator_peers,
+                vec![self
+                    .cached_client
+                    .as_ref()
+                    .expect("No cached client")
+                    .clone()],
+            )
+        });
+        let communicator_alias = Self::communicator_alias(self.pid, config_id, self.iteration_id);
+        let comm_alias_f = system.register_by_alias(&communicator, communicator_alias);
+        biconnect_components::<CommunicationPort, _, _>(&communicator, &paxos_replica)
+            .expect("Could not connect components!");
+        self.replicas.push(ConfigReplica {
+            config_id,
+            nodes: nodes.clone(),
+            replica: paxos_replica,
+            communicator,
+        });
+        self.active_config = config_id;
+        Handled::block_on(self, move |mut async_self| async move {
+            paxos_f
+                .await
+                .unwrap()
+                .expect("Timed out registering paxos component");
+            comm_f
+                .await
+                .unwrap()
+                .expect("Timed out registering communicator");
+            comm_alias_f
+                .await
+                .unwrap()
+                .expect("Timed out registering communicator alias");
+            if config_id == 1 {
+                async_self
+                    .partitioning_actor
+                    .take()
+                    .expect("No partitioning actor found!")
+                    .tell_serialised(
+                        PartitioningActorMsg::InitAck(async_self.iteration_id),
+                        async_self.deref_mut(),
+                    )
+                    .expect("Should serialise InitAck");
+            } else {
+                async_self.start_components();
+                let my_pid = async_self.pid;
+                for pid in nodes.iter().filter(|pid| **pid != my_pid) {
+                    let ap = async_self
+                        .peers
+                        .get(pid)
+                        .expect("No actorpath to peer")
+                        .clone();
+                    ap.tell_serialised(
+                        ReconfigurationMsg::Ready(config_id, my_pid),
+                        async_self.deref_mut(),
+                    )
+                    .expect("Should serialise Ready");
+                }
+            }
+        })
+    }
+
+    fn start_components(&self) {
+        let active = self.active_replica().expect("No paxos comp to start!");
+        self.ctx.system().start(&active.replica);
+        self.ctx.system().start(&active.communicator);
+    }
+
+    fn stop_components(&mut self) -> Handled {
+        self.stopped = true;
+        // peers of a configuration may never have started its replica, so the replicas do not
+        // wait for each other's stops and every node acks the client on its own
+        self.cached_client
+            .as_ref()
+            .expect("No cached client!")
+            .tell_serialised(NetStopMsg::Peer(self.pid), self)
+            .expect("Should serialise StopMsg");
+        let stop_fs: Vec<_> = self
+            .replicas
+            .iter()
+            .map(|r| {
+                r.replica
+                    .actor_ref()
+                    .ask_with(|p| PaxosReplicaMsg::Stop(Ask::new(p, ())))
+            })
+            .collect();
+        Handled::block_on(self, move |_| async move {
+            for stop_f in stop_fs {
+                stop_f.await.expect("Failed to stop PaxosReplica");
+            }
+        })
+    }
+
+    fn kill_components(&mut self, ask: Ask<(), Done>) -> Handled {
+        let system = self.ctx.system();
+        let mut kill_futures = Vec::with_capacity(2 * self.replicas.len());
+        for r in self.replicas.drain(..) {
+            kill_futures.push(system.kill_notify(r.replica));
+            kill_futures.push(system.kill_notify(r.communicator));
+        }
+        if let Some(timer) = self.transfer.take().and_then(|t| t.timer) {
+            self.cancel_timer(timer);
+        }
+        Handled::block_on(self, move |_| async move {
+            for f in kill_futures {
+                f.await.expect("Failed to kill");
+            }
+            ask.reply(Done).expect("Failed to reply done");
+        })
+    }
+
+    fn handle_reconfig(
+        &mut self,
+        config_id: u32,
+        ss: StopSign<Ballot>,
+        mut sequence: Vec<Entry<Ballot>>,
+        was_leader: bool,
+    ) -> Handled {
+        info!(
+            self.ctx.log(),
+            "Configuration {} stopped, next: {:?}", config_id, ss.nodes
+        );
+        let old_nodes = self
+            .replicas
+            .iter()
+            .find(|r| r.config_id == config_id)
+            .expect("No replica of stopped configuration")
+            .nodes
+            .clone();
+        if self.transfer.is_some() {
+            self.transfer_suffix.append(&mut sequence);
+        } else {
+            self.prev_sequence.append(&mut sequence);
+            self.answer_held_requests();
+        }
+        if was_leader {
+            if self.transfer.is_some() {
+                // the new nodes need the length of the whole sequence
+                self.deferred_init = Some((ss.clone(), old_nodes));
+            } else {
+                self.send_reconfig_init(&ss, &old_nodes);
+            }
+        }
+        self.latest_config = (ss.config_id, ss.nodes.clone());
+        self.current_leader = 0;
+        self.active_config = 0;
+        if ss.nodes.contains(&self.pid) && !self.stopped {
+            let skip_leader = Self::normalise_leader(ss.skip_prepare_use_leader);
+            // installed once the transfer is complete otherwise
+            let kv_store = match self.transfer {
+                None => Some(Self::apply_sequence(&self.prev_sequence)),
+                Some(_) => None,
+            };
+            return self.create_components(ss.config_id, ss.nodes, skip_leader, kv_store);
+        }
+        Handled::Ok
+    }
+
+    fn send_reconfig_init(&mut self, ss: &StopSign<Ballot>, old_nodes: &[u64]) {
+        let (new_nodes, continued_nodes): (Vec<u64>, Vec<u64>) =
+            ss.nodes.iter().partition(|pid| !old_nodes.contains(pid));
+        let sequence = match self.reconfig_policy {
+            ReconfigurationPolicy::Eager => self.prev_sequence.clone(),
+            ReconfigurationPolicy::Pull => vec![],
+        };
+        let init = ReconfigInit {
+            config_id: ss.config_id,
+            nodes: ss.nodes.clone(),
+            continued_nodes,
+            skip_prepare_use_leader: ss.skip_prepare_use_leader.clone(),
+            seq_len: self.prev_sequence.len() as u64,
+            sequence,
+            from: self.pid,
+        };
+        for pid in new_nodes {
+            self.peers
+                .get(&pid)
+                .expect("No actorpath to new node")
+                .tell_serialised(ReconfigurationMsg::Init(init.clone()), self)
+                .expect("Should serialise ReconfigInit");
+        }
+    }
+
+    // the stopped configuration proposes the leader with the prio round, BLE ballots also carry its pid
+    fn normalise_leader(leader: Option<Leader<Ballot>>) -> Option<Leader<Ballot>> {
+        leader.map(|l| Leader::with(l.pid, Ballot::with(l.round.n, l.pid)))
+    }
+
+    fn handle_reconfig_init(&mut self, r: ReconfigInit) -> Handled {
+        if r.config_id <= self.latest_config.0 || self.stopped {
+            return Handled::Ok;
+        }
+        debug!(
+            self.ctx.log(),
+            "Got ReconfigInit for configuration {} from {}", r.config_id, r.from
+        );
+        self.latest_config = (r.config_id, r.nodes.clone());
+        let kv_store = match self.reconfig_policy {
+            ReconfigurationPolicy::Eager => {
+                assert_eq!(r.sequence.len() as u64, r.seq_len);
+                self.prev_sequence = r.sequence;
+                Some(Self::apply_sequence(&self.prev_sequence))
+            }
+            ReconfigurationPolicy::Pull => {
+                let mut sources = r.continued_nodes.clone();
+                if !sources.contains(&r.from) {
+                    sources.push(r.from);
+                }
+                let n = sources.len() as u64;
+                let segment_len = (r.seq_len + n - 1) / n;
+                let segments = (0..n)
+                    .map(|i| {
+                        (
+                            i * segment_len,
+                            std::cmp::min((i + 1) * segment_len, r.seq_len),
+                        )
+                    })
+                    .filter(|(from, to)| from < to)
+                    .collect();
+                self.transfer = Some(SequenceTransfer {
+                    config_id: r.config_id,
+                    sources,
+                    segments,
+                    received: HashMap::new(),
+                    attempt: 0,
+                    timer: None,
+                });
+                self.request_segments();
+                None
+            }
+        };
+        let skip_leader = Self::normalise_leader(r.skip_prepare_use_leader);
+        self.create_components(r.config_id, r.nodes, skip_leader, kv_store)
+    }
+
+    fn request_segments(&mut self) {
+        let transfer = match self.transfer.as_mut() {
+            Some(t) => t,
+            None => return,
+        };
+        if transfer.segments.is_empty() {
+            self.complete_transfer();
+            return;
+        }
+        let mut requests = vec![];
+        for (i, (from_idx, to_idx)) in transfer.segments.iter().enumerate() {
+            if transfer.received.contains_key(from_idx) {
+                continue;
+            }
+            let source = transfer.sources[(i + transfer.attempt) % transfer.sources.len()];
+            let sr = SequenceRequest {
+                config_id: transfer.config_id,
+                from_idx: *from_idx,
+                to_idx: *to_idx,
+                requestor: self.pid,
+            };
+            requests.push((source, sr));
+        }
+        for (source, sr) in requests {
+            self.peers
+                .get(&source)
+                .expect("No actorpath to source of sequence")
+                .tell_serialised(ReconfigurationMsg::SequenceRequest(sr), self)
+                .expect("Should serialise SequenceRequest");
+        }
+        let transfer_timeout = self.ctx.config()["paxos"]["transfer_timeout"]
+            .as_duration()
+            .expect("Failed to load transfer_timeout");
+        let timer = self.schedule_once(transfer_timeout, move |c, _| {
+            if let Some(t) = c.transfer.as_mut() {
+                t.timer = None;
+                t.attempt += 1;
+            }
+            c.request_segments();
+            Handled::Ok
+        });
+        self.transfer.as_mut().unwrap().timer = Some(timer);
+    }
+
+    fn handle_segment(&mut self, s: SequenceSegment) {
+        let complete = match self.transfer.as_mut() {
+            Some(t) if t.config_id == s.config_id => {
+                t.received.entry(s.from_idx).or_insert(s.entries);
+                t.received.len() == t.segments.len()
+            }
+            _ => false,
+        };
+        if complete {
+            self.complete_transfer();
+        }
+    }
+
+    fn complete_transfer(&mut self) {
+        let mut transfer = self.transfer.take().expect("No sequence transfer");
+        if let Some(timer) = transfer.timer.take() {
+            self.cancel_timer(timer);
+        }
+        for (from_idx, _) in &transfer.segments {
+            let mut entries = transfer.received.remove(from_idx).unwrap();
+            self.prev_sequence.append(&mut entries);
+        }
+        self.prev_sequence.append(&mut self.transfer_suffix);
+        info!(
+            self.ctx.log(),
+            "Transferred sequence of len {} for configuration {}",
+            self.prev_sequence.len(),
+            transfer.config_id
+        );
+        // the replica applies its decided entries on top of the transferred sequence
+        if let Some(r) = self
+            .replicas
+            .iter()
+            .find(|r| r.config_id == transfer.config_id)
+        {
+            let kv_store = Self::apply_sequence(&self.prev_sequence);
+            r.replica
+                .actor_ref()
+                .tell(PaxosReplicaMsg::InstallStore(kv_store));
+        }
+        self.answer_held_requests();
+        if let Some((ss, old_nodes)) = self.deferred_init.take() {
+            self.send_reconfig_init(&ss, &old_nodes);
+        }
+    }
+
+    fn handle_sequence_request(&mut self, sr: SequenceRequest) {
+        if (self.prev_sequence.len() as u64) < sr.to_idx {
+            self.held_requests.push(sr);
+            return;
+        }
+        let entries = self.prev_sequence[sr.from_idx as usize..sr.to_idx as usize].to_vec();
+        let segment = SequenceSegment {
+            config_id: sr.config_id,
+            from_idx: sr.from_idx,
+            entries,
+        };
+        self.peers
+            .get(&sr.requestor)
+            .expect("No actorpath to requestor")
+            .tell_serialised(ReconfigurationMsg::SequenceSegment(segment), self)
+            .expect("Should serialise SequenceSegment");
+    }
+
+    fn answer_held_requests(&mut self) {
+        let held = std::mem::take(&mut self.held_requests);
+        for sr in held {
+            self.handle_sequence_request(sr);
+        }
+    }
+
+    fn handle_ready(&mut self, config_id: u32, pid: u64) {
+        match self.replicas.iter().find(|r| r.config_id == config_id) {
+            Some(r) => r.replica.actor_ref().tell(PaxosReplicaMsg::PeerReady(pid)),
+            None => {
+                self.ready_peers.entry(config_id).or_default().insert(pid);
+            }
+        }
+    }
+
+    fn apply_sequence(entries: &[Entry<Ballot>]) -> KVStore {
+        let mut kv_store = KVStore::default();
+        for entry in entries {
+            if let Entry::Normal(data) = entry {
+                for (id, watermark, command) in deserialise_commands(data) {
+                    kv_store.apply(id, watermark, &command);
+                }
+            }
+        }
+        kv_store
+    }
+
+    fn get_decided_ids(entries: &[Entry<Ballot>]) -> Vec<u64> {
+        entries
+            .iter()
+            .filter_map(|e| match e {
+                Entry::Normal(data) => Some(data.as_slice().get_u64()),
+                Entry::StopSign(_) => None,
+            })
+            .collect()
+    }
+
+    fn reject(&self, sender: &ActorPath, am: AtomicBroadcastMsg, reason: RejectReason) {
+        let id = match am {
+            AtomicBroadcastMsg::Proposal(p) if p.reconfig.is_none() => p.data.as_slice().get_u64(),
+            AtomicBroadcastMsg::ReadRequest(id) => id,
+            _ => return,
+        };
+        let pr = ProposalRejected::with(id, reason);
+        sender
+            .tell_serialised(AtomicBroadcastMsg::ProposalRejected(pr), self)
+            .expect("Should serialise ProposalRejected");
+    }
+
+    fn handle_client_msg(&mut self, sender: ActorPath, am: AtomicBroadcastMsg) {
+        if let AtomicBroadcastMsg::TransferLeader(target) = am {
+            if target == self.pid {
+                if let Some(active) = self.active_replica() {
+                    active.replica.actor_ref().tell(PaxosReplicaMsg::Takeover);
+                }
+            } else if let Some(ap) = self.peers.get(&target) {
+                ap.tell_serialised(AtomicBroadcastMsg::TransferLeader(target), self)
+                    .expect("Should serialise TransferLeader");
+            }
+            return;
+        }
+        if self.active_config == 0 {
+            // e.g. removed in a reconfiguration, or a new node that has not joined yet
+            match am {
+                AtomicBroadcastMsg::Proposal(p) if p.reconfig.is_some() => {
+                    let (_, nodes) = &self.latest_config;
+                    if let Some(ap) = nodes.iter().find_map(|pid| self.peers.get(pid)) {
+                        ap.tell_serialised(AtomicBroadcastMsg::Proposal(p), self)
+                            .expect("Should serialise Proposal");
+                    }
+                }
+                am => self.reject(&sender, am, RejectReason::Dropped),
+            }
+            return;
+        }
+        if self.current_leader == self.pid || self.current_leader == 0 {
+            // if no leader, let the replica hold back
+            let replica_msg = match am {
+                AtomicBroadcastMsg::Proposal(p) => Some(PaxosReplicaMsg::Propose(p)),
+                AtomicBroadcastMsg::ReadRequest(id) => Some(PaxosReplicaMsg::Read(id)),
+                _ => None,
+            };
+            if let Some(replica_msg) = replica_msg {
+                self.active_replica()
+                    .unwrap()
+                    .replica
+                    .actor_ref()
+                    .tell(replica_msg);
+            }
+        } else {
+            let leader = self
+                .peers
+                .get(&self.current_leader)
+                .unwrap_or_else(|| {
+                    panic!(
+                        "Could not get leader's actorpath. Pid: {}",
+                        self.current_leader
+                    )
+                })
+                .clone();
+            // redirect the client to the leader, other messages are forwarded
+            match am {
+                AtomicBroadcastMsg::Proposal(p) if p.reconfig.is_some() => {
+                    leader
+                        .tell_serialised(AtomicBroadcastMsg::Proposal(p), self)
+                        .expect("Should serialise");
+                }
+                am => self.reject(&sender, am, RejectReason::NotLeader(self.current_leader)),
+            }
+        }
+    }
+}
+
+impl ComponentLifecycle for PaxosComp {}
+
+impl ReplicatedLog for PaxosComp {
+    const PATH: &'static str = PAXOS_PATH;
+
+    fn create(initial_config: Vec<u64>, reconfig_policy: &str) -> Self {
+        let reconfig_policy = match reconfig_policy {
+            "none" | "pull" => ReconfigurationPolicy::Pull,
+            "eager" => ReconfigurationPolicy::Eager,
+            unknown => panic!("Got unknown Paxos transfer policy: {}", unknown),
+        };
+        PaxosComp::with(initial_config, reconfig_policy)
+    }
+
+    fn handle_init(&mut self, init: Init, partitioning_actor: ActorPath) -> Handled {
+        info!(self.ctx.log(), "Paxos got init, pid: {}", init.pid);
+        self.current_leader = 0;
+        self.iteration_id = init.init_id;
+        let my_pid = init.pid as u64;
+        let ser_client = init
+            .init_data
+            .expect("Init should include ClientComp's actorpath");
+        // Paxos runs a single group and thereby has a single client
+        let mut buf = ser_client.as_slice();
+        let client =
+            ActorPath::deserialise(&mut buf).expect("Failed to deserialise Client's actorpath");
+        assert!(!buf.has_remaining(), "Expected a single client for Paxos");
+        self.cached_client = Some(client);
+        self.peers = init
+            .nodes
+            .into_iter()
+            .enumerate()
+            .map(|(idx, ap)| (idx as u64 + 1, ap))
+            .filter(|(pid, _)| pid != &my_pid)
+            .collect();
+        self.pid = my_pid;
+        self.partitioning_actor = Some(partitioning_actor);
+        self.stopped = false;
+        self.active_config = 0;
+        self.latest_config = (1, self.initial_config.clone());
+        self.prev_sequence.clear();
+        self.transfer_suffix.clear();
+        self.held_requests.clear();
+        self.deferred_init = None;
+        self.ready_peers.clear();
+        if self.initial_config.contains(&my_pid) {
+            return self.create_components(
+                1,
+                self.initial_config.clone(),
+                None,
+                Some(KVStore::default()),
+            );
+        }
+        // joins once a reconfiguration adds it
+        self.partitioning_actor
+            .take()
+            .unwrap()
+            .tell_serialised(PartitioningActorMsg::InitAck(self.iteration_id), self)
+            .expect("Should serialise InitAck");
+        Handled::Ok
+    }
+
+    fn handle_run(&mut self) -> Handled {
+        if self.active_config == 1 {
+            self.start_components();
+        }
+        Handled::Ok
+    }
+
+    fn handle_stop(&mut self) -> Handled {
+        if self.stopped {
+            return Handled::Ok;
+        }
+        self.stop_components()
+    }
+
+    fn get_sequence(ask: Ask<(), SequenceResp>) -> PaxosCompMsg {
+        PaxosCompMsg::GetSequence(ask)
+    }
+
+    fn kill(ask: Ask<(), Done>) -> PaxosCompMsg {
+        PaxosCompMsg::KillComponents(ask)
+    }
+}
+
+impl Actor for PaxosComp {
+    type Message = PaxosCompMsg;
+
+    fn receive_local(&mut self, msg: Self::Message) -> Handled {
+        match msg {
+            PaxosCompMsg::Leader(config_id, pid) => {
+                if config_id != self.active_config {
+                    return Handled::Ok;
+                }
+                debug!(
+                    self.ctx.log(),
+                    "Node {} became leader in configuration {}", pid, config_id
+                );
+                if pid == self.pid && self.current_leader != pid {
+                    self.cached_client
+                        .as_ref()
+                        .expect("No cached client!")
+                        .tell_serialised(AtomicBroadcastMsg::FirstLeader(pid), self)
+                        .expect("Should serialise FirstLeader");
+                }
+                self.current_leader = pid;
+            }
+            PaxosCompMsg::ForwardReconfig(leader_pid, reconfig) => {
+                self.current_leader = leader_pid;
+                let mut data: Vec<u8> = Vec::with_capacity(8);
+                data.put_u64(RECONFIG_ID);
+                let p = Proposal::reconfiguration(data, reconfig);
+                self.peers
+                    .get(&leader_pid)
+                    .expect("No actorpath to current leader")
+                    .tell_serialised(AtomicBroadcastMsg::Proposal(p), self)
+                    .expect("Should serialise")
+            }
+            PaxosCompMsg::Reconfig(config_id, ss, sequence, was_leader) => {
+                return self.handle_reconfig(config_id, ss, sequence, was_leader);
+            }
+            PaxosCompMsg::KillComponents(ask) => {
+                return self.kill_components(ask);
+            }
+            PaxosCompMsg::GetSequence(ask) => {
+                let mut sequence = Self::get_decided_ids(&self.prev_sequence);
+                let active = match self.active_replica() {
+                    // the decided entries of the new configuration follow the transferred prefix
+                    Some(active) if self.transfer.is_none() => Some(active.replica.actor_ref()),
+                    _ => None,
+                };
+                return Handled::block_on(self, move |async_self| async move {
+                    if let Some(replica) = active {
+                        let mut seq = replica
+                            .ask_with(|promise| PaxosReplicaMsg::SequenceReq(Ask::new(promise, ())))
+                            .await
+                            .expect("failed to ask");
+                        sequence.append(&mut seq);
+                    }
+                    let sr = SequenceResp::with(async_self.pid, sequence);
+                    ask.reply(sr).expect("Failed to reply SequenceResp");
+                });
+            }
+        }
+        Handled::Ok
+    }
+
+    fn receive_network(&mut self, m: NetMessage) -> Handled {
+        match m.data.ser_id {
+            ATOMICBCAST_ID => {
+                if !self.stopped {
+                    let sender = m.sender.clone();
+                    let am = m
+                        .try_deserialise_unchecked::<AtomicBroadcastMsg, AtomicBroadcastDeser>()
+                        .expect("Should be AtomicBroadcastMsg!");
+                    self.handle_client_msg(sender, am);
+                }
+            }
+            _ => {
+                let NetMessage { sender, data, .. } = m;
+                match_deser! {data {
+                    msg(r): ReconfigurationMsg [using ReconfigSer] => {
+                        match r {
+                            ReconfigurationMsg::Init(init) => return self.handle_reconfig_init(init),
+                            ReconfigurationMsg::Ready(config_id, pid) => self.handle_ready(config_id, pid),
+                            ReconfigurationMsg::SequenceRequest(sr) => self.handle_sequence_request(sr),
+                            ReconfigurationMsg::SequenceSegment(s) => self.handle_segment(s),
+                        }
+                    },
+                    msg(p): PartitioningActorMsg [using PartitioningActorSer] => {
+                        return self.handle_partitioning_msg(p, sender);
+                    },
+                    msg(client_stop): NetStopMsg [using StopMsgDeser] => {
+                        if let NetStopMsg::Client = client_stop {
+                            assert!(!self.stopped);
+                            return self.stop_components();
+                        }
+                    },
+                    err(e) => error!(self.ctx.log(), "Error deserialising msg: {:?}", e),
+                    default(_) => unimplemented!("Expected either ReconfigurationMsg, PartitioningActorMsg or NetStopMsg!"),
+                }
+                }
+            }
+        }
+        Handled::Ok
+    }
+}
+
+#[derive(Debug)]
+pub enum PaxosReplicaMsg {
+    Propose(Proposal),
+    Read(u64),
+    Takeover, // outbid the current leader, e.g. for a leader transfer
+    PeerReady(u64),
+    Stop(Ask<(), ()>),
+    SequenceReq(Ask<(), Vec<u64>>),
+    InstallStore(KVStore),
+}
+
+struct BallotLeaderElection {
+    pid: u64,
+    majority: usize,
+    ballot: Ballot,
+    round: u32,
+    replies: Vec<(Ballot, bool)>, // (ballot, majority connected) of the peers that replied this round
+    majority_connected: bool,
+    leader: Option<Ballot>,
+}
+
+impl BallotLeaderElection {
+    fn with(pid: u64, num_nodes: usize, leader: Option<&Leader<Ballot>>) -> Self {
+        let ballot = match leader {
+            Some(l) if l.pid == pid => l.round,
+            _ => Ballot::with(0, pid),
+        };
+        BallotLeaderElection {
+            pid,
+            majority: num_nodes / 2 + 1,
+            ballot,
+            round: 0,
+            replies: vec![],
+            majority_connected: true,
+            leader: leader.map(|l| l.round),
+        }
+    }
+
+    // ends the current round, returns the new leader if it changed
+    fn hb_timeout(&mut self) -> Option<Leader<Ballot>> {
+        let leader = if self.replies.len() + 1 >= self.majority {
+            self.majority_connected = true;
+            self.replies.push((self.ballot, true));
+            self.check_leader()
+        } else {
+            self.majority_connected = false;
+            None
+        };
+        self.replies.clear();
+        self.round += 1;
+        leader
+    }
+
+    fn check_leader(&mut self) -> Option<Leader<Ballot>> {
+        let top = self
+            .replies
+            .iter()
+            .filter(|(_, majority_connected)| *majority_connected)
+            .map(|(ballot, _)| *ballot)
+            .max()
+            .unwrap_or_default();
+        let current = self.leader.unwrap_or_default();
+        if top < current {
+            // did not hear from the leader, outbid it
+            self.ballot.n = current.n + 1;
+            self.leader = None;
+            None
+        } else if self.leader != Some(top) {
+            self.leader = Some(top);
+            Some(Leader::with(top.pid, top))
+        } else {
+            None
+        }
+    }
+
+    fn handle_reply(&mut self, round: u32, ballot: Ballot, majority_connected: bool) {
+        if round == self.round {
+            self.replies.push((ballot, majority_connected));
+        }
+    }
+
+    fn takeover(&mut self) {
+        let current = self.leader.unwrap_or_default();
+        if current.pid != self.pid {
+            self.ballot.n = std::cmp::max(current.n, self.ballot.n) + 1;
+        }
+    }
+}
+
+#[derive(ComponentDefinition)]
+pub struct PaxosReplica {
+    ctx: ComponentContext<Self>,
+    supervisor: ActorRef<PaxosCompMsg>,
+    communication_port: RequiredPort<CommunicationPort>,
+    config_id: u32,
+    pid: u64,
+    nodes: Vec<u64>,
+    peers: Vec<u64>,
+    paxos: Paxos<Ballot, MemorySequence<Ballot>, MemoryState<Ballot>>,
+    ble: BallotLeaderElection,
+    timers: Vec<ScheduledTimer>,
+    current_leader: u64, // the leader that has completed its prepare phase
+    had_leader: bool,
+    ready_peers: HashSet<u64>,
+    unready_msgs: HashMap<u64, Vec<RawPaxosMsg<Ballot>>>, // held until the peer has started this configuration
+    hb_proposals: Vec<Proposal>,                          // held back until there is a leader
+    max_pending_proposals: usize,
+    hb_reads: Vec<u64>,
+    reads: HashSet<u64>, // read ids this node proposed, the read is ordered through the log
+    prio_start_round: u32,
+    decided_idx: u64,
+    kv_store: Option<KVStore>, // None until the sequence of the previous configurations is transferred
+    stopped: bool,
+    stopsign_decided: bool,
+}
+
+impl PaxosReplica {
+    pub fn with(
+        config_id: u32,
+        pid: u64,
+        nodes: Vec<u64>,
+        peers: Vec<u64>,
+        skip_prepare_use_leader: Option<Leader<Ballot>>,
+        supervisor: ActorRef<PaxosCompMsg>,
+        max_inflight: usize,
+        max_pending_proposals: usize,
+        prio_start_round: u32,
+        ready_peers: HashSet<u64>,
+        kv_store: Option<KVStore>,
+    ) -> PaxosReplica {
+        let ble = BallotLeaderElection::with(pid, nodes.len(), skip_prepare_use_leader.as_ref());
+        let storage = Storage::with(MemorySequence::new(), MemoryState::new());
+        let paxos = Paxos::with(
+            config_id,
+            pid,
+            peers.clone(),
+            storage,
+            skip_prepare_use_leader,
+            max_inflight,
+        );
+        PaxosReplica {
+            ctx: ComponentContext::uninitialised(),
+            supervisor,
+            communication_port: RequiredPort::uninitialised(),
+            config_id,
+            pid,
+            nodes,
+            peers,
+            paxos,
+            ble,
+            timers: vec![],
+            current_leader: 0,
+            had_leader: false,
+            ready_peers,
+            unready_msgs: HashMap::new(),
+            hb_proposals: vec![],
+            max_pending_proposals,
+            hb_reads: vec![],
+            reads: HashSet::new(),
+            prio_start_round,
+            decided_idx: 0,
+            kv_store,
+            stopped: false,
+            stopsign_decided: false,
+        }
+    }
+
+    fn start_timers(&mut self) {
+        let config = self.ctx.config();
+        let outgoing_period = config["experiment"]["outgoing_period"]
+            .as_duration()
+            .expect("Failed to load outgoing_period");
+        let get_decided_period = config["paxos"]["get_decided_period"]
+            .as_duration()
+            .expect("Failed to load get_decided_period");
+        let ble_delta = config["paxos"]["ble_delta"]
+            .as_i64()
+            .expect("Failed to load ble_delta") as u64;
+        let outgoing_timer =
+            self.schedule_periodic(DELAY, outgoing_period, move |c, _| c.send_outgoing());
+        let decided_timer =
+            self.schedule_periodic(DELAY, get_decided_period, move |c, _| c.handle_decided());
+        let ble_timer =
+            self.schedule_periodic(DELAY, Duration::from_millis(ble_delta), move |c, _| {
+                c.hb_timeout()
+            });
+        self.timers = vec![outgoing_timer, decided_timer, ble_timer];
+    }
+
+    fn stop_timers(&mut self) {
+        for timer in std::mem::take(&mut self.timers) {
+            self.cancel_timer(timer);
+        }
+    }
+
+    fn send_outgoing(&mut self) -> Handled {
+        let msgs = self.paxos.get_outgoing_msgs();
+        for m in msgs {
+            if let PaxosMsg::AcceptSync(_) = m.msg {
+                // a majority has promised, i.e. this node completed its prepare phase
+                self.established_leader(self.pid);
+            }
+            if self.ready_peers.contains(&m.to) {
+                self.communication_port
+                    .trigger(CommunicatorMsg::RawPaxosMsg(m));
+            } else {
+                self.unready_msgs.entry(m.to).or_default().push(m);
+            }
+        }
+        Handled::Ok
+    }
+
+    fn peer_ready(&mut self, pid: u64) {
+        if self.ready_peers.insert(pid) {
+            for m in self.unready_msgs.remove(&pid).unwrap_or_default() {
+                self.communication_port
+                    .trigger(CommunicatorMsg::RawPaxosMsg(m));
+            }
+        }
+    }
+
+    fn hb_timeout(&mut self) -> Handled {
+        if let Some(l) = self.ble.hb_timeout() {
+            debug!(
+                self.ctx.log(),
+                "BLE elected {} in configuration {}", l.pid, self.config_id
+            );
+            self.paxos.handle_leader(l);
+        }
+        let round = self.ble.round;
+        for pid in &self.peers {
+            if self.ready_peers.contains(pid) {
+                let hb = BLEMessage::with(self.pid, *pid, HeartbeatMsg::Request(round));
+                self.communication_port.trigger(CommunicatorMsg::BLEMsg(hb));
+            }
+        }
+        Handled::Ok
+    }
+
+    fn handle_ble(&mut self, bm: BLEMessage) {
+        self.peer_ready(bm.from);
+        match bm.msg {
+            HeartbeatMsg::Request(round) => {
+                let reply =
+                    HeartbeatMsg::Reply(round, self.ble.ballot, self.ble.majority_connected);
+                let hb = BLEMessage::with(self.pid, bm.from, reply);
+                self.communication_port.trigger(CommunicatorMsg::BLEMsg(hb));
+            }
+            HeartbeatMsg::Reply(round, ballot, majority_connected) => {
+                self.ble.handle_reply(round, ballot, majority_connected);
+            }
+        }
+    }
+
+    fn handle_paxos_msg(&mut self, m: RawPaxosMsg<Ballot>) {
+        self.peer_ready(m.from);
+        match m.msg {
+            PaxosMsg::AcceptSync(_) | PaxosMsg::FirstAccept(_) => self.established_leader(m.from),
+            _ => {}
+        }
+        self.paxos.handle(m);
+    }
+
+    fn established_leader(&mut self, pid: u64) {
+        if pid == self.current_leader {
+            return;
+        }
+        self.current_leader = pid;
+        self.supervisor
+            .tell(PaxosCompMsg::Leader(self.config_id, pid));
+        if pid == self.pid && !self.had_leader && self.config_id > 1 {
+            // the client completes the reconfiguration once the new configuration has a leader
+            self.reply_reconfiguration();
+        }
+        self.had_leader = true;
+        let proposals = std::mem::take(&mut self.hb_proposals);
+        for p in proposals {
+            self.propose(p);
+        }
+        let reads = std::mem::take(&mut self.hb_reads);
+        for id in reads {
+            self.read(id);
+        }
+    }
+
+    fn hold_proposal(&mut self, proposal: Proposal) {
+        if proposal.reconfig.is_none() && self.hb_proposals.len() >= self.max_pending_proposals {
+            self.reject_proposal(&proposal.data, RejectReason::Dropped);
+        } else {
+            self.hb_proposals.push(proposal);
+        }
+    }
+
+    fn reject_proposal(&mut self, mut data: &[u8], reason: RejectReason) {
+        let pr = ProposalRejected::with(data.get_u64(), reason);
+        self.communication_port
+            .trigger(CommunicatorMsg::ProposalRejected(0, pr));
+    }
+
+    fn propose(&mut self, proposal: Proposal) {
+        if self.current_leader == 0 {
+            self.hold_proposal(proposal);
+            return;
+        }
+        match proposal.reconfig {
+            Some((mut nodes, _)) => {
+                if self.current_leader != self.pid {
+                    self.supervisor.tell(PaxosCompMsg::ForwardReconfig(
+                        self.current_leader,
+                        (nodes, vec![]),
+                    ));
+                    return;
+                }
+                let mut current_nodes = self.nodes.clone();
+                current_nodes.sort_unstable();
+                nodes.sort_unstable();
+                if nodes == current_nodes {
+                    // retry of a reconfiguration this configuration resulted from
+                    self.reply_reconfiguration();
+                    return;
+                }
+                let prio_round = Ballot::with(self.prio_start_round, 0);
+                if let Err(e) = self.paxos.propose_reconfiguration(nodes, Some(prio_round)) {
+                    // e.g. a retry while the stopsign is pending
+                    debug!(self.ctx.log(), "Failed to propose reconfiguration: {:?}", e);
+                }
+            }
+            None => {
+                let data = proposal.data;
+                if let Err(ProposeErr::Normal(data)) = self.paxos.propose_normal(data) {
+                    self.reject_proposal(&data, RejectReason::Dropped);
+                }
+            }
+        }
+    }
+
+    fn read(&mut self, id: u64) {
+        if self.current_leader == 0 {
+            self.hb_reads.push(id);
+            return;
+        }
+        if self.current_leader != self.pid {
+            let pr = ProposalRejected::with(id, RejectReason::NotLeader(self.current_leader));
+            self.communication_port
+                .trigger(CommunicatorMsg::ProposalRejected(0, pr));
+            return;
+        }
+        // ordered as a command that leaves the store untouched and does not move the watermark
+        let mut data: Vec<u8> = Vec::with_capacity(17);
+        KVCommand::Append.serialise(id, 0, &mut data);
+        match self.paxos.propose_normal(data) {
+            Ok(_) => {
+                self.reads.insert(id);
+            }
+            Err(_) => {
+                let pr = ProposalRejected::with(id, RejectReason::Dropped);
+                self.communication_port
+                    .trigger(CommunicatorMsg::ProposalRejected(0, pr));
+            }
+        }
+    }
+
+    fn handle_decided(&mut self) -> Handled {
+        if self.kv_store.is_none() {
+            // the decided entries are kept until they can be applied
+            return Handled::Ok;
+        }
+        let decided = self.paxos.get_decided_entries().to_vec();
+        for entry in decided {
+            self.decided_idx += 1;
+            match entry {
+                Entry::Normal(data) => {
+                    // every replica applies the commands and the leader responds
+                    let is_leader = self.current_leader == self.pid;
+                    for (id, watermark, command) in deserialise_commands(&data) {
+                        let kv_store = self.kv_store.as_mut().unwrap();
+                        let response = kv_store.apply(id, watermark, &command);
+                        if !is_leader {
+                            continue;
+                        }
+                        if self.reads.remove(&id) {
+                            let rr = ReadResp::with(id, self.decided_idx, self.pid);
+                            self.communication_port
+                                .trigger(CommunicatorMsg::ReadResponse(0, rr));
+                        } else if let Some(response) = response {
+                            let mut data: Vec<u8> = Vec::with_capacity(14);
+                            data.put_u64(id);
+                            response.serialise(&mut data);
+                            let pr = ProposalResp::with(data, self.pid);
+                            self.communication_port
+                                .trigger(CommunicatorMsg::ProposalResponse(0, pr));
+                        }
+                    }
+                }
+                Entry::StopSign(ss) => {
+                    if self.stopsign_decided {
+                        continue;
+                    }
+                    self.stopsign_decided = true;
+                    let was_leader = self.current_leader == self.pid;
+                    let sequence = self.paxos.stop_and_get_sequence().get_sequence();
+                    let decided = sequence[..self.decided_idx as usize].to_vec();
+                    // keeps serving the peers that have not decided the stopsign yet
+                    self.supervisor.tell(PaxosCompMsg::Reconfig(
+                        self.config_id,
+                        ss,
+                        decided,
+                        was_leader,
+                    ));
+                }
+            }
+        }
+        Handled::Ok
+    }
+
+    fn reply_reconfiguration(&mut self) {
+        let mut data: Vec<u8> = Vec::with_capacity(8 + 4 + 8 * self.nodes.len() + 4);
+        data.put_u64(RECONFIG_ID);
+        data.put_u32(self.nodes.len() as u32);
+        for pid in &self.nodes {
+            data.put_u64(*pid);
+        }
+        data.put_u32(0); // no learners
+        let pr = ProposalResp::with(data, self.pid);
+        self.communication_port
+            .trigger(CommunicatorMsg::ProposalResponse(0, pr));
+    }
+}
+
+impl ComponentLifecycle for PaxosReplica {
+    fn on_start(&mut self) -> Handled {
+        let bc = BufferConfig::default();
+        self.ctx.borrow().init_buffers(Some(bc), None);
+        let skip_leader = self.paxos.get_current_leader();
+        if skip_leader != 0 {
+            // continued from the stopped configuration without a prepare phase
+            self.established_leader(skip_leader);
+        }
+        self.start_timers();
+        Handled::Ok
+    }
+
+    fn on_kill(&mut self) -> Handled {
+        self.stop_timers();
+        Handled::Ok
+    }
+}
+
+impl Actor for PaxosReplica {
+    type Message = PaxosReplicaMsg;
+
+    fn receive_local(&mut self, msg: Self::Message) -> Handled {
+        match msg {
+            PaxosReplicaMsg::Propose(p) => {
+                if !self.stopped {
+                    self.propose(p);
+                }
+            }
+            PaxosReplicaMsg::Read(id) => {
+                if !self.stopped {
+                    self.read(id);
+                }
+            }
+            PaxosReplicaMsg::Takeover => {
+                if !self.stopped && !self.stopsign_decided {
+                    info!(
+                        self.ctx.log(),
+                        "Taking over leadership from {}", self.current_leader
+                    );
+                    self.ble.takeover();
+                }
+            }
+            PaxosReplicaMsg::PeerReady(pid) => self.peer_ready(pid),
+            PaxosReplicaMsg::Stop(ask) => {
+                self.stop_timers();
+                self.stopped = true;
+                ask.reply(()).expect("Failed to reply Stop ask");
+            }
+            PaxosReplicaMsg::SequenceReq(sr) => {
+                let sequence = self.paxos.get_sequence();
+                let decided = &sequence[..self.decided_idx as usize];
+                let ids = PaxosComp::get_decided_ids(decided);
+                info!(self.ctx.log(), "Got SequenceReq: my seq_len={}", ids.len());
+                sr.reply(ids).expect("Failed to respond SequenceReq ask");
+            }
+            PaxosReplicaMsg::InstallStore(kv_store) => {
+                self.kv_store = Some(kv_store);
+            }
+        }
+        Handled::Ok
+    }
+
+    fn receive_network(&mut self, _msg: NetMessage) -> Handled {
+        // ignore
+        Handled::Ok
+    }
+}
+
+impl Require<CommunicationPort> for PaxosReplica {
+    fn handle(&mut self, msg: AtomicBroadcastCompMsg) -> Handled {
+        if self.stopped {
+            return Handled::Ok;
+        }
+        match msg {
+            AtomicBroadcastCompMsg::RawPaxosMsg(pm) => self.handle_paxos_msg(pm),
+            AtomicBroadcastCompMsg::BLEMsg(bm) => self.handle_ble(bm),
+            _ => {}
+        }
+        Handled::Ok
+    }
+}
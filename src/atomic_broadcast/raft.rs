@@ -1,23 +1,26 @@
 extern crate raft as tikv_raft;
 
 use super::{
+    kv::{command_ids, deserialise_commands, KVStore},
     messages::{StopMsg as NetStopMsg, StopMsgDeser, *},
     storage::raft::*, partitioning_actor::KVOperation,
 };
 use crate::atomic_broadcast::{
         communicator::{AtomicBroadcastCompMsg, CommunicationPort, Communicator, CommunicatorMsg},
-        partitioning_actor::{PartitioningActorMsg, PartitioningActorSer},
+        partitioning_actor::{Init, PartitioningActorMsg, PartitioningActorSer},
+        replicated_log::ReplicatedLog,
         serialiser_ids::ATOMICBCAST_ID,
-        atomic_broadcast::{Done, SequenceResp}
+        atomic_broadcast::{Done, SequenceResp, RAFT_PATH}
     };
 use hashbrown::{HashMap, HashSet};
 use kompact::prelude::*;
+use hocon::Hocon;
 use protobuf::Message as PbMessage;
 use rand::Rng;
 use std::{borrow::Borrow, clone::Clone, marker::Send, ops::DerefMut, sync::Arc, time::Duration, os::unix::prelude::CommandExt};
 use tikv_raft::{
     prelude::{Message as TikvRaftMsg, *},
-    StateRole,
+    ReadOnlyOption, ReadState, StateRole,
 };
 
 const COMMUNICATOR: &str = "communicator";
@@ -25,10 +28,11 @@ const DELAY: Duration = Duration::from_millis(0);
 
 #[derive(Debug)]
 pub enum RaftCompMsg {
-    Leader(bool, u64),
+    Leader(u64, bool, u64), // (group, notify_client, pid)
     ForwardReconfig(u64, (Vec<u64>, Vec<u64>)),
     KillComponents(Ask<(), Done>),
     GetSequence(Ask<(), SequenceResp>),
+    TransferLeader(u64),
 }
 
 #[derive(ComponentDefinition)]
@@ -39,14 +43,16 @@ where
     ctx: ComponentContext<Self>,
     pid: u64,
     initial_config: Vec<u64>,
-    raft_replica: Option<Arc<Component<RaftReplica<S>>>>,
-    communicator: Option<Arc<Component<Communicator>>>,
+    raft_replicas: Vec<Arc<Component<RaftReplica<S>>>>, // one per group, indexed by group id
+    communicator: Option<Arc<Component<Communicator>>>, // shared by the groups
+    log_writers: Vec<Arc<Component<LogWriter<S>>>>,
     peers: HashMap<u64, ActorPath>,
     iteration_id: u32,
     stopped: bool,
     partitioning_actor: Option<ActorPath>,
-    cached_client: Option<ActorPath>,
-    current_leader: u64,
+    cached_clients: Vec<ActorPath>, // the client of each group
+    client_stops: usize,
+    current_leaders: Vec<u64>,
     reconfig_policy: ReconfigurationPolicy,
 }
 
@@ -59,58 +65,22 @@ where
             ctx: ComponentContext::uninitialised(),
             pid: 0,
             initial_config,
-            raft_replica: None,
+            raft_replicas: vec![],
             communicator: None,
+            log_writers: vec![],
             peers: HashMap::new(),
             iteration_id: 0,
             stopped: false,
             partitioning_actor: None,
-            cached_client: None,
-            current_leader: 0,
+            cached_clients: vec![],
+            client_stops: 0,
+            current_leaders: vec![],
             reconfig_policy,
         }
     }
 
-    fn create_rawraft_config(&self) -> Config {
-        let config = self.ctx.config();
-        let max_inflight_msgs = config["experiment"]["max_inflight"]
-            .as_i64()
-            .expect("Failed to load max_inflight") as usize;
-        let election_timeout = config["experiment"]["election_timeout"]
-            .as_i64()
-            .expect("Failed to load election_timeout") as usize;
-        let tick_period = config["raft"]["tick_period"]
-            .as_i64()
-            .expect("Failed to load tick_period") as usize;
-        let leader_hb_period = config["raft"]["leader_hb_period"]
-            .as_i64()
-            .expect("Failed to load leader_hb_period") as usize;
-        let max_batch_size = config["raft"]["max_batch_size"]
-            .as_i64()
-            .expect("Failed to load max_batch_size") as u64;
-        let pre_vote = config["raft"]["pre_vote"]
-            .as_bool()
-            .expect("Failed to load pre_vote");
-        let check_quorum = config["raft"]["check_quorum"]
-            .as_bool()
-            .expect("Failed to load check_quorum");
-        // convert from ms to logical clock ticks
-        let election_tick = election_timeout / tick_period;
-        let heartbeat_tick = leader_hb_period / tick_period;
-        // info!(self.ctx.log(), "RawRaft config: election_tick={}, heartbeat_tick={}", election_tick, heartbeat_tick);
-        let max_size_per_msg = max_batch_size;
-        let c = Config {
-            id: self.pid,
-            election_tick,  // number of ticks without HB before starting election
-            heartbeat_tick, // leader sends HB every heartbeat_tick
-            max_inflight_msgs,
-            max_size_per_msg,
-            pre_vote,
-            check_quorum,
-            ..Default::default()
-        };
-        assert!(c.validate().is_ok(), "Invalid RawRaft config");
-        c
+    fn create_rawraft_config(&self, applied: u64) -> Config {
+        rawraft_config(self.ctx.config(), self.pid, applied)
     }
 
     fn create_components(&mut self) -> Handled {
@@ -137,43 +107,118 @@ where
         }
 
         let system = self.ctx.system();
-        let dir = &format!("./diskstorage_node{}", self.pid);
-        let conf_state: (Vec<u64>, Vec<u64>) = (self.initial_config.clone(), vec![]);
-        let store = S::new_with_conf_state(Some(dir), conf_state);
-        let raw_raft =
-            RawNode::new(&self.create_rawraft_config(), store).expect("Failed to create tikv Raft");
+        let storage_dir = self.ctx.config()["raft"]["storage_dir"]
+            .as_string()
+            .expect("Failed to load storage_dir");
+        let recover_storage = self.ctx.config()["raft"]["recover_storage"]
+            .as_bool()
+            .expect("Failed to load recover_storage");
+        let durability = self.ctx.config()["raft"]["durability"]
+            .as_string()
+            .expect("Failed to load durability")
+            .parse::<Durability>()
+            .expect("Failed to parse durability");
+        let async_log_writer = self.ctx.config()["raft"]["async_log_writer"]
+            .as_bool()
+            .expect("Failed to load async_log_writer");
+        let group_commit_period = self.ctx.config()["raft"]["group_commit_period"]
+            .as_duration()
+            .expect("Failed to load group_commit_period");
         let max_inflight = self.ctx.config()["experiment"]["max_inflight"]
             .as_i64()
             .expect("Failed to load max_inflight") as usize;
-        let (raft_replica, raft_f) = system.create_and_register(|| {
-            RaftReplica::with(
-                raw_raft,
-                self.actor_ref(),
-                self.reconfig_policy.clone(),
-                self.peers.len(),
-                max_inflight,
-            )
-        });
+        let snapshot_interval = self.ctx.config()["raft"]["snapshot_interval"]
+            .as_i64()
+            .expect("Failed to load snapshot_interval") as u64;
+        let compaction_interval = self.ctx.config()["raft"]["compaction_interval"]
+            .as_i64()
+            .expect("Failed to load compaction_interval") as u64;
+        let max_pending_proposals = self.ctx.config()["raft"]["max_pending_proposals"]
+            .as_i64()
+            .expect("Failed to load max_pending_proposals") as usize;
+        let batch_size = self.ctx.config()["raft"]["batch_size"]
+            .as_i64()
+            .expect("Failed to load batch_size") as usize;
+        let batch_timeout = self.ctx.config()["raft"]["batch_timeout"]
+            .as_duration()
+            .expect("Failed to load batch_timeout");
+        let num_groups = self.cached_clients.len() as u64;
         let (communicator, comm_f) = system.create_and_register(|| {
-            Communicator::with(
-                communicator_peers,
-                self.cached_client
-                    .as_ref()
-                    .expect("No cached client")
-                    .clone(),
-            )
+            Communicator::with(communicator_peers, self.cached_clients.clone())
         });
         let communicator_alias = format!("{}{}-{}", COMMUNICATOR, self.pid, self.iteration_id);
         let comm_alias_f = system.register_by_alias(&communicator, communicator_alias);
-        biconnect_components::<CommunicationPort, _, _>(&communicator, &raft_replica)
-            .expect("Could not connect components!");
-        self.raft_replica = Some(raft_replica);
+        let mut raft_replicas = Vec::with_capacity(num_groups as usize);
+        let mut log_writers = vec![];
+        let mut raft_fs = Vec::with_capacity(num_groups as usize);
+        for group in 0..num_groups {
+            let dir = &format!(
+                "{}/diskstorage_node{}_group{}",
+                storage_dir, self.pid, group
+            );
+            let conf_state: (Vec<u64>, Vec<u64>) = (self.initial_config.clone(), vec![]);
+            let recovered = if recover_storage {
+                S::recover(Some(dir))
+            } else {
+                None
+            };
+            let mut store = match recovered {
+                Some(store) => {
+                    info!(self.ctx.log(), "Recovered raft storage from {}", dir);
+                    store
+                }
+                None => S::new_with_conf_state(Some(dir), conf_state),
+            };
+            let log_writer = if async_log_writer {
+                // appends only reach the page cache, the log writer syncs them
+                store.set_durability(Durability::None);
+                let store = store.clone();
+                Some(system.create_dedicated(|| LogWriter::with(store, group_commit_period)))
+            } else {
+                store.set_durability(durability);
+                None
+            };
+            // entries up to the latest snapshot are already reflected in the replica's state
+            let applied = store
+                .snapshot(0)
+                .expect("Failed to get snapshot")
+                .get_metadata()
+                .index;
+            let raw_raft = RawNode::new(&self.create_rawraft_config(applied), store)
+                .expect("Failed to create tikv Raft");
+            let (raft_replica, raft_f) = system.create_and_register(|| {
+                RaftReplica::with(
+                    raw_raft,
+                    group,
+                    self.actor_ref(),
+                    self.reconfig_policy.clone(),
+                    self.peers.len(),
+                    max_inflight,
+                    max_pending_proposals,
+                    snapshot_interval,
+                    compaction_interval,
+                    batch_size,
+                    batch_timeout,
+                    log_writer.as_ref().map(|log_writer| log_writer.actor_ref()),
+                )
+            });
+            biconnect_components::<CommunicationPort, _, _>(&communicator, &raft_replica)
+                .expect("Could not connect components!");
+            raft_replicas.push(raft_replica);
+            log_writers.extend(log_writer);
+            raft_fs.push(raft_f);
+        }
+        self.raft_replicas = raft_replicas;
         self.communicator = Some(communicator);
+        self.log_writers = log_writers;
+        self.current_leaders = vec![0; num_groups as usize];
         Handled::block_on(self, move |mut async_self| async move {
-            raft_f
-                .await
-                .unwrap()
-                .expect("Timed out registering raft component");
+            for raft_f in raft_fs {
+                raft_f
+                    .await
+                    .unwrap()
+                    .expect("Timed out registering raft component");
+            }
             comm_f
                 .await
                 .unwrap()
@@ -195,35 +240,48 @@ where
     }
 
     fn start_components(&self) {
-        let raft = self.raft_replica.as_ref().expect("No raft comp to start!");
+        assert!(!self.raft_replicas.is_empty(), "No raft comp to start!");
         let communicator = self
             .communicator
             .as_ref()
             .expect("No communicator to start!");
-        self.ctx.system().start(raft);
+        for raft in &self.raft_replicas {
+            self.ctx.system().start(raft);
+        }
         self.ctx.system().start(communicator);
+        for log_writer in &self.log_writers {
+            self.ctx.system().start(log_writer);
+        }
     }
 
     fn stop_components(&mut self) -> Handled {
         self.stopped = true;
         // info!(self.ctx.log(), "Stopping components");
-        let raft = self
-            .raft_replica
-            .as_ref()
-            .expect("Got stop but no Raft replica");
-        let stop_f = raft
-            .actor_ref()
-            .ask_with(|p| RaftReplicaMsg::Stop(Ask::new(p, ())));
+        assert!(
+            !self.raft_replicas.is_empty(),
+            "Got stop but no Raft replica"
+        );
+        let stop_fs: Vec<_> = self
+            .raft_replicas
+            .iter()
+            .map(|raft| {
+                raft.actor_ref()
+                    .ask_with(|p| RaftReplicaMsg::Stop(Ask::new(p, ())))
+            })
+            .collect();
         Handled::block_on(self, move |_| async move {
-            stop_f.await.expect("Failed to stop RaftReplica");
+            for stop_f in stop_fs {
+                stop_f.await.expect("Failed to stop RaftReplica");
+            }
         })
     }
 
     fn kill_components(&mut self, ask: Ask<(), Done>) -> Handled {
         let system = self.ctx.system();
-        let mut kill_futures = Vec::with_capacity(2);
+        let mut kill_futures =
+            Vec::with_capacity(1 + self.raft_replicas.len() + self.log_writers.len());
 
-        if let Some(raft) = self.raft_replica.take() {
+        for raft in self.raft_replicas.drain(..) {
             let kill_raft = system.kill_notify(raft);
             kill_futures.push(kill_raft);
         }
@@ -231,6 +289,10 @@ where
             let kill_comm = system.kill_notify(communicator);
             kill_futures.push(kill_comm);
         }
+        for log_writer in self.log_writers.drain(..) {
+            let kill_writer = system.kill_notify(log_writer);
+            kill_futures.push(kill_writer);
+        }
 
         Handled::block_on(self, move |_| async move {
             for f in kill_futures {
@@ -243,6 +305,82 @@ where
 
 impl<S> ComponentLifecycle for RaftComp<S> where S: RaftStorage + Send + Clone + 'static {}
 
+impl<S> ReplicatedLog for RaftComp<S>
+where
+    S: RaftStorage + Send + Clone + 'static,
+{
+    const PATH: &'static str = RAFT_PATH;
+
+    fn create(initial_config: Vec<u64>, reconfig_policy: &str) -> Self {
+        let reconfig_policy = match reconfig_policy {
+            "none" | "replace-follower" => ReconfigurationPolicy::ReplaceFollower,
+            "replace-leader" => ReconfigurationPolicy::ReplaceLeader,
+            "promote-learners" => ReconfigurationPolicy::PromoteLearners,
+            "single-step" => ReconfigurationPolicy::SingleStep,
+            unknown => panic!("Got unknown Raft transfer policy: {}", unknown),
+        };
+        RaftComp::with(initial_config, reconfig_policy)
+    }
+
+    fn handle_init(&mut self, init: Init, partitioning_actor: ActorPath) -> Handled {
+        info!(self.ctx.log(), "Raft got init, pid: {}", init.pid);
+        self.iteration_id = init.init_id;
+        let my_pid = init.pid as u64;
+        let ser_clients = init
+            .init_data
+            .expect("Init should include the ClientComps' actorpaths");
+        // one client per group, in group order
+        let mut buf = ser_clients.as_slice();
+        let mut clients = vec![];
+        while buf.has_remaining() {
+            let client =
+                ActorPath::deserialise(&mut buf).expect("Failed to deserialise Client's actorpath");
+            clients.push(client);
+        }
+        let num_groups = self.ctx.config()["raft"]["num_groups"]
+            .as_i64()
+            .expect("Failed to load num_groups") as usize;
+        assert_eq!(
+            clients.len(),
+            num_groups,
+            "Expected one client per Raft group"
+        );
+        self.cached_clients = clients;
+        self.client_stops = 0;
+        self.peers = init
+            .nodes
+            .into_iter()
+            .enumerate()
+            .map(|(idx, ap)| (idx as u64 + 1, ap))
+            .filter(|(pid, _)| pid != &my_pid)
+            .collect();
+        self.pid = my_pid;
+        self.partitioning_actor = Some(partitioning_actor);
+        self.stopped = false;
+        self.create_components()
+    }
+
+    fn handle_run(&mut self) -> Handled {
+        self.start_components();
+        Handled::Ok
+    }
+
+    fn handle_stop(&mut self) -> Handled {
+        if self.stopped {
+            return Handled::Ok;
+        }
+        self.stop_components()
+    }
+
+    fn get_sequence(ask: Ask<(), SequenceResp>) -> RaftCompMsg {
+        RaftCompMsg::GetSequence(ask)
+    }
+
+    fn kill(ask: Ask<(), Done>) -> RaftCompMsg {
+        RaftCompMsg::KillComponents(ask)
+    }
+}
+
 impl<S> Actor for RaftComp<S>
 where
     S: RaftStorage + Send + Clone + 'static,
@@ -251,19 +389,26 @@ where
 
     fn receive_local(&mut self, msg: Self::Message) -> Handled {
         match msg {
-            RaftCompMsg::Leader(notify_client, pid) => {
-                debug!(self.ctx.log(), "Node {} became leader", pid);
-                if notify_client {
-                    self.cached_client
-                        .as_ref()
+            RaftCompMsg::Leader(group, notify_client, pid) => {
+                debug!(
+                    self.ctx.log(),
+                    "Node {} became leader of group {}", pid, group
+                );
+                let group = group as usize;
+                // a new leader also announces itself, e.g. to complete a leader transfer
+                let became_leader = pid == self.pid && self.current_leaders[group] != pid;
+                if notify_client || became_leader {
+                    self.cached_clients
+                        .get(group)
                         .expect("No cached client!")
                         .tell_serialised(AtomicBroadcastMsg::FirstLeader(pid), self)
                         .expect("Should serialise FirstLeader");
                 }
-                self.current_leader = pid
+                self.current_leaders[group] = pid
             }
             RaftCompMsg::ForwardReconfig(leader_pid, reconfig) => {
-                self.current_leader = leader_pid;
+                // only the first group is reconfigured
+                self.current_leaders[0] = leader_pid;
                 let mut data: Vec<u8> = Vec::with_capacity(8);
                 data.put_u64(RECONFIG_ID);
                 let p = Proposal::reconfiguration(data, reconfig);
@@ -277,17 +422,34 @@ where
                 return self.kill_components(ask);
             }
             RaftCompMsg::GetSequence(ask) => {
+                assert!(!self.raft_replicas.is_empty(), "No raft replica");
+                let seq_fs: Vec<_> = self
+                    .raft_replicas
+                    .iter()
+                    .map(|raft_replica| {
+                        raft_replica
+                            .actor_ref()
+                            .ask_with(|promise| RaftReplicaMsg::SequenceReq(Ask::new(promise, ())))
+                    })
+                    .collect();
                 return Handled::block_on(self, move | async_self| async move {
-                    let raft_replica = async_self.raft_replica.as_ref().expect("No raft replica");
-                    let seq = raft_replica
-                    .actor_ref()
-                    .ask_with(|promise| {
-                        RaftReplicaMsg::SequenceReq(Ask::new(promise, ()))
-                    }).await.expect("failed to ask");
+                    // the groups' sequences one after another
+                    let mut seq = vec![];
+                    for seq_f in seq_fs {
+                        seq.append(&mut seq_f.await.expect("failed to ask"));
+                    }
                     let sr = SequenceResp::with(async_self.pid, seq);
                     ask.reply(sr).expect("Failed to reply SequenceResp");
                 });
             }
+            RaftCompMsg::TransferLeader(target) => {
+                assert!(!self.raft_replicas.is_empty(), "No active RaftComp");
+                for raft_replica in &self.raft_replicas {
+                    raft_replica
+                        .actor_ref()
+                        .tell(RaftReplicaMsg::TransferLeader(target));
+                }
+            }
         }
         Handled::Ok
     }
@@ -296,26 +458,60 @@ where
         match m.data.ser_id {
             ATOMICBCAST_ID => {
                 if !self.stopped {
-                    if self.current_leader == self.pid || self.current_leader == 0 {
+                    let num_groups = self.raft_replicas.len() as u64;
+                    assert!(num_groups > 0, "No active RaftComp");
+                    let sender = m.sender.clone();
+                    let am = m
+                        .try_deserialise_unchecked::<AtomicBroadcastMsg, AtomicBroadcastDeser>()
+                        .expect("Should be AtomicBroadcastMsg!");
+                    // operations go to the group of their key
+                    let group = match &am {
+                        AtomicBroadcastMsg::Proposal(p) => {
+                            group_of(p.data.as_slice().get_u64(), num_groups)
+                        }
+                        AtomicBroadcastMsg::ReadRequest(id) => group_of(*id, num_groups),
+                        _ => 0,
+                    };
+                    let group = group as usize;
+                    let current_leader = self.current_leaders[group];
+                    if current_leader == self.pid || current_leader == 0 {
                         // if no leader, let raftcomp hold back
-                        if let AtomicBroadcastMsg::Proposal(p) = m
-                            .try_deserialise_unchecked::<AtomicBroadcastMsg, AtomicBroadcastDeser>()
-                            .expect("Should be AtomicBroadcastMsg!")
-                        {
-                            self.raft_replica
-                                .as_ref()
-                                .expect("No active RaftComp")
-                                .actor_ref()
-                                .tell(RaftReplicaMsg::Propose(p));
+                        let replica_msg = match am {
+                            AtomicBroadcastMsg::Proposal(p) => Some(RaftReplicaMsg::Propose(p)),
+                            AtomicBroadcastMsg::ReadRequest(id) => Some(RaftReplicaMsg::Read(id)),
+                            AtomicBroadcastMsg::TransferLeader(target) => {
+                                Some(RaftReplicaMsg::TransferLeader(target))
+                            }
+                            _ => None,
+                        };
+                        if let Some(replica_msg) = replica_msg {
+                            self.raft_replicas[group].actor_ref().tell(replica_msg);
+                        }
+                    } else {
+                        let leader = self
+                            .peers
+                            .get(&current_leader)
+                            .unwrap_or_else(|| {
+                                panic!("Could not get leader's actorpath. Pid: {}", current_leader)
+                            })
+                            .clone();
+                        // redirect the client to the leader, other messages are forwarded
+                        let redirect_id = match am {
+                            AtomicBroadcastMsg::Proposal(p) if p.reconfig.is_none() => {
+                                Some(p.data.as_slice().get_u64())
+                            }
+                            AtomicBroadcastMsg::ReadRequest(id) => Some(id),
+                            am => {
+                                leader.tell_serialised(am, self).expect("Should serialise");
+                                None
+                            }
+                        };
+                        if let Some(id) = redirect_id {
+                            let pr = ProposalRejected::with(id, RejectReason::NotLeader(current_leader));
+                            sender
+                                .tell_serialised(AtomicBroadcastMsg::ProposalRejected(pr), self)
+                                .expect("Should serialise ProposalRejected");
                         }
-                    } else if self.current_leader > 0 {
-                        let leader = self.peers.get(&self.current_leader).unwrap_or_else(|| {
-                            panic!(
-                                "Could not get leader's actorpath. Pid: {}",
-                                self.current_leader
-                            )
-                        });
-                        leader.forward_with_original_sender(m, self);
                     }
                 }
             }
@@ -324,34 +520,17 @@ where
                 println!("pre match_deser!");
                 match_deser! {data {
                     msg(p): PartitioningActorMsg [using PartitioningActorSer] => {
-                        match p {
-                            PartitioningActorMsg::Init(init) => {
-                                info!(self.ctx.log(), "Raft got init, pid: {}", init.pid);
-                                self.current_leader = 0;
-                                self.iteration_id = init.init_id;
-                                let my_pid = init.pid as u64;
-                                let ser_client = init.init_data.expect("Init should include ClientComp's actorpath");
-                                let client = ActorPath::deserialise(&mut ser_client.as_slice()).expect("Failed to deserialise Client's actorpath");
-                                self.cached_client = Some(client);
-                                
-                                self.peers = init.nodes.into_iter().enumerate().map(|(idx, ap)| (idx as u64 + 1, ap)).filter(|(pid, _)| pid != &my_pid).collect();
-                                self.pid = my_pid;
-                                self.partitioning_actor = Some(sender);
-                                self.stopped = false;
-                                let handled = self.create_components();
-                                return handled;
-                            },
-                            PartitioningActorMsg::Run => {
-                                self.start_components();
-                            },
-                            _ => {},
-                        }
+                        return self.handle_partitioning_msg(p, sender);
                     },
                     msg(client_stop): NetStopMsg [using StopMsgDeser] => {
                         if let NetStopMsg::Client = client_stop {
                             // info!(self.ctx.log(), "Got client stop");
                             assert!(!self.stopped);
-                            return self.stop_components();
+                            // every group's client stops the node, it stops once all have
+                            self.client_stops += 1;
+                            if self.client_stops == self.cached_clients.len() {
+                                return self.stop_components();
+                            }
                         }
                     },
                     err(e) => error!(self.ctx.log(), "Error deserialising msg: {:?}", e),
@@ -364,24 +543,123 @@ where
     }
 }
 
+// shared with the master, which records the effective values with the results
+pub fn rawraft_config(config: &Hocon, id: u64, applied: u64) -> Config {
+    let max_inflight_msgs = config["experiment"]["max_inflight"]
+        .as_i64()
+        .expect("Failed to load max_inflight") as usize;
+    let election_timeout = config["experiment"]["election_timeout"]
+        .as_i64()
+        .expect("Failed to load election_timeout") as usize;
+    let tick_period = config["raft"]["tick_period"]
+        .as_i64()
+        .expect("Failed to load tick_period") as usize;
+    let leader_hb_period = config["raft"]["leader_hb_period"]
+        .as_i64()
+        .expect("Failed to load leader_hb_period") as usize;
+    let max_batch_size = config["raft"]["max_batch_size"]
+        .as_i64()
+        .expect("Failed to load max_batch_size") as u64;
+    let pre_vote = config["raft"]["pre_vote"]
+        .as_bool()
+        .expect("Failed to load pre_vote");
+    let check_quorum = config["raft"]["check_quorum"]
+        .as_bool()
+        .expect("Failed to load check_quorum");
+    let skip_bcast_commit = config["raft"]["skip_bcast_commit"]
+        .as_bool()
+        .expect("Failed to load skip_bcast_commit");
+    let batch_append = config["raft"]["batch_append"]
+        .as_bool()
+        .expect("Failed to load batch_append");
+    let min_election_tick = config["raft"]["min_election_tick"]
+        .as_i64()
+        .expect("Failed to load min_election_tick") as usize;
+    let max_election_tick = config["raft"]["max_election_tick"]
+        .as_i64()
+        .expect("Failed to load max_election_tick") as usize;
+    let read_only_option = match config["raft"]["read_only_option"]
+        .as_string()
+        .expect("Failed to load read_only_option")
+        .as_ref()
+    {
+        "safe" => ReadOnlyOption::Safe,
+        // the leader answers from its lease, which check_quorum keeps from outliving its leadership
+        "lease-based" => ReadOnlyOption::LeaseBased,
+        unknown => panic!("Got unknown read_only_option: {}", unknown),
+    };
+    // convert from ms to logical clock ticks
+    let election_tick = election_timeout / tick_period;
+    let heartbeat_tick = leader_hb_period / tick_period;
+    // info!(self.ctx.log(), "RawRaft config: election_tick={}, heartbeat_tick={}", election_tick, heartbeat_tick);
+    let max_size_per_msg = max_batch_size;
+    let c = Config {
+        id,
+        election_tick,  // number of ticks without HB before starting election
+        heartbeat_tick, // leader sends HB every heartbeat_tick
+        max_inflight_msgs,
+        max_size_per_msg,
+        pre_vote,
+        check_quorum,
+        read_only_option,
+        skip_bcast_commit,
+        batch_append,
+        min_election_tick,
+        max_election_tick,
+        applied,
+        ..Default::default()
+    };
+    if let Err(e) = c.validate() {
+        panic!("Invalid RawRaft config: {:?}", e);
+    }
+    c
+}
+
+// raft-rs options without a Config field at the pinned revision, so they cannot be set from the
+// config file. Runs record them as unmapped instead of silently using the defaults.
+const UNMAPPED_RAFT_OPTIONS: [&str; 2] = ["max_committed_size_per_ready", "priority"];
+
+pub fn describe_rawraft_config(c: &Config) -> String {
+    format!(
+        "election_tick={},heartbeat_tick={},min_election_tick={},max_election_tick={},max_inflight_msgs={},max_size_per_msg={},pre_vote={},check_quorum={},read_only_option={:?},skip_bcast_commit={},batch_append={},unmapped={}",
+        c.election_tick,
+        c.heartbeat_tick,
+        c.min_election_tick(),
+        c.max_election_tick(),
+        c.max_inflight_msgs,
+        c.max_size_per_msg,
+        c.pre_vote,
+        c.check_quorum,
+        c.read_only_option,
+        c.skip_bcast_commit,
+        c.batch_append,
+        UNMAPPED_RAFT_OPTIONS.join("|")
+    )
+}
+
 #[derive(Debug)]
 pub enum RaftReplicaMsg {
     Propose(Proposal),
+    Read(u64),
+    TransferLeader(u64),
     Stop(Ask<(), ()>),
     SequenceReq(Ask<(), Vec<u64>>),
+    LogSynced(u64),
 }
 
 #[derive(Clone, Debug)]
 pub enum ReconfigurationPolicy {
     ReplaceLeader,
     ReplaceFollower,
+    PromoteLearners, // new nodes join as learners and become voters once they have caught up
+    SingleStep,      // single-server AddNode/RemoveNode changes applied one at a time instead of joint consensus
 }
 
 #[derive(Debug, PartialEq)]
 enum ReconfigurationState {
     None,
     Pending,
-    Finished,
+    CatchingUp,
     Removed,
 }
 
@@ -400,6 +678,7 @@ where
     supervisor: ActorRef<RaftCompMsg>,
     state: State,
     raw_raft: RawNode<S>,
+    group: u64,
     communication_port: RequiredPort<CommunicationPort>,
     timers: Option<(ScheduledTimer, ScheduledTimer)>,
     reconfig_state: ReconfigurationState,
@@ -408,9 +687,38 @@ where
     num_peers: usize,
     stopped: bool,
     stopped_peers: HashSet<u64>,
-    hb_proposals: Vec<Proposal>,
+    hb_proposals: Vec<Proposal>, // held back until there is a leader
+    max_pending_proposals: usize, // bound of hb_proposals and hb_reads, normal proposals and reads beyond it are rejected
+    hb_reads: Vec<u64>, // held back until there is a leader that has committed an entry in its term
+    pending_reads: Vec<(u64, u64)>, // (read index, read id) waiting for the applied index to catch up
     max_inflight: usize,
     stop_ask: Option<Ask<(), ()>>,
+    snapshot_interval: u64, // number of applied entries between snapshots, 0 disables snapshots
+    applied: u64,
+    snapshot_index: u64,
+    snapshot_sequence: Vec<u64>, // decided proposal ids covered by the latest snapshot
+    kv_store: KVStore,           // the committed commands applied up to the applied index
+    compaction_interval: u64, // number of applied entries between log compactions, 0 disables compaction
+    compacted_index: u64,
+    promotion: Option<(Vec<u64>, Vec<u64>)>, // configuration to apply once the learners have caught up
+    conf_changes: Option<Vec<ConfChange>>, // remaining single-step changes while this node drives a reconfiguration
+    batch_size: usize, // number of proposals committed as one entry, 1 disables batching
+    batch_timeout: Duration, // how long a proposal waits for the batch to fill up
+    batch: Vec<u8>,    // commands of the buffered proposals
+    batch_len: usize,
+    batch_timer: Option<ScheduledTimer>,
+    log_writer: Option<ActorRef<LogWriterMsg>>,
+    sync_seq: u64,   // sequence number of the latest sync requested from the log writer
+    synced_seq: u64, // sequence number of the latest sync the log writer completed
+    unsynced_readies: Vec<(u64, PendingReady)>, // held back until the log is synced up to the sequence number
+    queued_index: u64, // last committed index taken from a Ready, the next Ready starts after it
+}
+
+// the part of a Ready that has to wait until its entries and hard state are synced
+struct PendingReady {
+    messages: Vec<TikvRaftMsg>,
+    read_states: Vec<ReadState>,
+    committed_entries: Vec<Entry>,
 }
 
 impl<S> ComponentLifecycle for RaftReplica<S>
@@ -448,6 +756,21 @@ where
                     self.propose(p);
                 }
             }
+            RaftReplicaMsg::Read(id) => {
+                if self.reconfig_state != ReconfigurationState::Removed {
+                    self.read(id);
+                }
+            }
+            RaftReplicaMsg::TransferLeader(target) => {
+                if self.reconfig_state != ReconfigurationState::Removed {
+                    info!(
+                        self.ctx.log(),
+                        "Transferring leadership from {} to {}", self.raw_raft.raft.leader_id, target
+                    );
+                    // followers forward it to the leader
+                    self.raw_raft.transfer_leader(target);
+                }
+            }
             RaftReplicaMsg::Stop(ask) => {
                 println!("STOP REPLICA");
                 self.communication_port
@@ -463,18 +786,18 @@ where
             RaftReplicaMsg::SequenceReq(sr) => {
                 println!("pre raw_raft.raft.raft_log.all_entries()?????");
                 let raft_entries: Vec<Entry> = self.raw_raft.raft.raft_log.all_entries();
-                let mut sequence: Vec<u64> = Vec::with_capacity(raft_entries.len());
-                let mut unique = HashSet::new();
+                let mut sequence: Vec<u64> =
+                    Vec::with_capacity(self.snapshot_sequence.len() + raft_entries.len());
+                // entries up to the snapshot index are only represented by the snapshot
+                sequence.extend_from_slice(&self.snapshot_sequence);
+                let snapshot_index = self.snapshot_index;
+                let log_entries: Vec<Entry> = raft_entries
+                    .into_iter()
+                    .filter(|e| e.index > snapshot_index)
+                    .collect();
                 println!("pre for entry in raft entries?????");
-                for entry in raft_entries {
-                    if entry.get_entry_type() == EntryType::EntryNormal && !&entry.data.is_empty() {
-                        let id = entry.data.as_slice().get_u64();
-                        if id != 0 {
-                            sequence.push(id);
-                        }
-                        unique.insert(id);
-                    }
-                }
+                sequence.append(&mut Self::get_decided_ids(&log_entries));
+                let unique: HashSet<&u64> = sequence.iter().collect();
                 info!(
                     self.ctx.log(),
                     "Got SequenceReq: my seq_len={}. Unique={}",
@@ -484,6 +807,18 @@ where
                 sr.reply(sequence)
                     .expect("Failed to respond SequenceReq ask");
             }
+            RaftReplicaMsg::LogSynced(seq) => {
+                self.synced_seq = seq;
+                let num_synced = self
+                    .unsynced_readies
+                    .iter()
+                    .take_while(|(s, _)| *s <= seq)
+                    .count();
+                let synced: Vec<_> = self.unsynced_readies.drain(..num_synced).collect();
+                for (_, pending) in synced {
+                    self.handle_ready(pending);
+                }
+            }
         }
         Handled::Ok
     }
@@ -500,8 +835,10 @@ where
 {
     fn handle(&mut self, msg: AtomicBroadcastCompMsg) -> Handled {
         match msg {
-            AtomicBroadcastCompMsg::RawRaftMsg(rm)
-                if !self.stopped && self.reconfig_state != ReconfigurationState::Removed =>
+            AtomicBroadcastCompMsg::RawRaftMsg(group, rm)
+                if group == self.group
+                    && !self.stopped
+                    && self.reconfig_state != ReconfigurationState::Removed =>
             {
                 self.step(rm);
             }
@@ -532,16 +869,32 @@ where
 {
     pub fn with(
         raw_raft: RawNode<S>,
+        group: u64,
         replica: ActorRef<RaftCompMsg>,
         reconfig_policy: ReconfigurationPolicy,
         num_peers: usize,
         max_inflight: usize,
+        max_pending_proposals: usize,
+        snapshot_interval: u64,
+        compaction_interval: u64,
+        batch_size: usize,
+        batch_timeout: Duration,
+        log_writer: Option<ActorRef<LogWriterMsg>>,
     ) -> RaftReplica<S> {
+        let snapshot = raw_raft
+            .raft
+            .raft_log
+            .store
+            .snapshot(0)
+            .expect("Failed to get snapshot");
+        let snapshot_index = snapshot.get_metadata().index;
+        let (snapshot_sequence, kv_store) = Self::deserialise_snapshot(snapshot.get_data());
         RaftReplica {
             ctx: ComponentContext::uninitialised(),
             supervisor: replica,
             state: State::Election,
             raw_raft,
+            group,
             communication_port: RequiredPort::uninitialised(),
             timers: None,
             reconfig_state: ReconfigurationState::None,
@@ -551,9 +904,125 @@ where
             stopped_peers: HashSet::new(),
             num_peers,
             hb_proposals: vec![],
+            max_pending_proposals,
+            hb_reads: vec![],
+            pending_reads: vec![],
             max_inflight,
             stop_ask: None,
+            snapshot_interval,
+            applied: snapshot_index,
+            snapshot_index,
+            snapshot_sequence,
+            kv_store,
+            compaction_interval,
+            compacted_index: snapshot_index,
+            promotion: None,
+            conf_changes: None,
+            batch_size,
+            batch_timeout,
+            batch: vec![],
+            batch_len: 0,
+            batch_timer: None,
+            log_writer,
+            sync_seq: 0,
+            synced_seq: 0,
+            unsynced_readies: vec![],
+            queued_index: snapshot_index,
+        }
+    }
+
+    fn get_decided_ids(entries: &[Entry]) -> Vec<u64> {
+        let mut ids = Vec::with_capacity(entries.len());
+        for entry in entries {
+            if entry.get_entry_type() == EntryType::EntryNormal && !&entry.data.is_empty() {
+                // a batched entry holds several commands
+                for id in command_ids(&entry.data) {
+                    if id != 0 {
+                        ids.push(id);
+                    }
+                }
+            }
+        }
+        ids
+    }
+
+    fn serialise_snapshot(sequence: &[u64], kv_store: &KVStore) -> Vec<u8> {
+        let mut data: Vec<u8> = Vec::with_capacity(8 + 8 * sequence.len());
+        data.put_u64(sequence.len() as u64);
+        for id in sequence {
+            data.put_u64(*id);
         }
+        kv_store.serialise(&mut data);
+        data
+    }
+
+    fn deserialise_snapshot(data: &[u8]) -> (Vec<u64>, KVStore) {
+        if data.is_empty() {
+            // no snapshot yet
+            return (vec![], KVStore::default());
+        }
+        let mut buf = data;
+        let len = buf.get_u64() as usize;
+        let mut sequence = Vec::with_capacity(len);
+        for _ in 0..len {
+            sequence.push(buf.get_u64());
+        }
+        let kv_store = KVStore::deserialise(&mut buf);
+        (sequence, kv_store)
+    }
+
+    fn create_snapshot(&mut self) {
+        // the snapshot data is the decided sequence and the store up to the applied index
+        let applied = self.applied;
+        let store = self.raw_raft.mut_store();
+        let first_index = store.first_index().expect("Failed to get first index");
+        let from = std::cmp::max(first_index, self.snapshot_index + 1);
+        let entries = store
+            .entries(from, applied + 1, None)
+            .expect("Failed to read applied entries");
+        let mut sequence = self.snapshot_sequence.clone();
+        sequence.append(&mut Self::get_decided_ids(&entries));
+        let data = Self::serialise_snapshot(&sequence, &self.kv_store);
+        match store.create_snapshot(applied, data) {
+            Ok(_) => {
+                debug!(self.ctx.log(), "Created snapshot at index {}", applied);
+                self.snapshot_index = applied;
+                self.snapshot_sequence = sequence;
+            }
+            Err(e) => error!(
+                self.ctx.log(),
+                "Failed to create snapshot at index {}: {:?}", applied, e
+            ),
+        }
+    }
+
+    fn compact_log(&mut self) {
+        // compacted entries must be covered by a snapshot so lagging followers can catch up
+        if self.snapshot_index < self.applied {
+            self.create_snapshot();
+        }
+        let compact_index = self.snapshot_index + 1;
+        match self.raw_raft.mut_store().compact(compact_index) {
+            Ok(_) => {
+                debug!(self.ctx.log(), "Compacted log up to index {}", compact_index);
+                self.compacted_index = self.applied;
+            }
+            Err(e) => error!(
+                self.ctx.log(),
+                "Failed to compact log up to index {}: {:?}", compact_index, e
+            ),
+        }
+    }
+
+    fn restore_snapshot(&mut self, snapshot: &Snapshot) {
+        let index = snapshot.get_metadata().index;
+        debug!(self.ctx.log(), "Installing snapshot at index {}", index);
+        let (sequence, kv_store) = Self::deserialise_snapshot(snapshot.get_data());
+        self.snapshot_sequence = sequence;
+        self.kv_store = kv_store;
+        self.snapshot_index = index;
+        self.applied = index;
+        self.compacted_index = index;
     }
 
     fn start_timers(&mut self) {
@@ -598,6 +1067,18 @@ where
                     self.propose(proposal);
                 }
             }
+            if !self.hb_reads.is_empty() {
+                let reads = std::mem::take(&mut self.hb_reads);
+                for id in reads {
+                    self.read(id);
+                }
+            }
+            if self.promotion.is_some() {
+                self.try_promote_learners();
+            }
+            if self.conf_changes.is_some() {
+                self.try_propose_next_conf_change();
+            }
             if leader != self.current_leader {
                 // info!(self.ctx.log(), "New leader: {}, old: {}", leader, self.current_leader);
                 self.current_leader = leader;
@@ -608,7 +1089,7 @@ where
                     false
                 };
                 self.supervisor
-                    .tell(RaftCompMsg::Leader(notify_client, leader));
+                    .tell(RaftCompMsg::Leader(self.group, notify_client, leader));
             }
         }
         Handled::Ok
@@ -618,13 +1099,67 @@ where
         let _ = self.raw_raft.step(msg);
     }
 
+    fn hold_proposal(&mut self, proposal: Proposal) {
+        if proposal.reconfig.is_none() && self.hb_proposals.len() >= self.max_pending_proposals {
+            self.reject_proposals(&proposal.data, RejectReason::Dropped);
+        } else {
+            self.hb_proposals.push(proposal);
+        }
+    }
+
+    fn hold_read(&mut self, id: u64) {
+        if self.hb_reads.len() >= self.max_pending_proposals {
+            let pr = ProposalRejected::with(id, RejectReason::Dropped);
+            self.communication_port
+                .trigger(CommunicatorMsg::ProposalRejected(self.group, pr));
+        } else {
+            self.hb_reads.push(id);
+        }
+    }
+
+    fn reject_proposals(&mut self, data: &[u8], reason: RejectReason) {
+        // data may hold several commands, e.g. a batch
+        for id in command_ids(data) {
+            let pr = ProposalRejected::with(id, reason.clone());
+            self.communication_port
+                .trigger(CommunicatorMsg::ProposalRejected(self.group, pr));
+        }
+    }
+
+    // proposals that raft-rs refuses are rejected so the client retries them right away
+    fn propose_entry(&mut self, data: Vec<u8>) {
+        if let Err(e) = self.raw_raft.propose(vec![], data.clone()) {
+            let leader = self.raw_raft.raft.leader_id;
+            debug!(
+                self.ctx.log(),
+                "Failed to propose: {:?}. leader: {}, lead_transferee: {:?}",
+                e,
+                leader,
+                self.raw_raft.raft.lead_transferee
+            );
+            let reason = if leader != self.raw_raft.raft.id {
+                RejectReason::NotLeader(leader)
+            } else {
+                RejectReason::Dropped
+            };
+            self.reject_proposals(&data, reason);
+        }
+    }
+
     fn propose(&mut self, proposal: Proposal) {
         if self.raw_raft.raft.leader_id == 0 {
-            self.hb_proposals.push(proposal);
+            self.hold_proposal(proposal);
             return;
         }
         match proposal.reconfig {
             Some(mut reconfig) => {
+                if self.reconfig_state == ReconfigurationState::CatchingUp {
+                    // e.g. a new leader that has to finish promoting the learners
+                    if self.raw_raft.raft.state == StateRole::Leader && self.promotion.is_none() {
+                        self.promotion = Some(reconfig);
+                    }
+                    return;
+                }
                 if let ReconfigurationState::None = self.reconfig_state {
                     let leader_pid = self.raw_raft.raft.leader_id;
                     if leader_pid != self.raw_raft.raft.id {
@@ -632,8 +1167,21 @@ where
                             .tell(RaftCompMsg::ForwardReconfig(leader_pid, reconfig));
                         return;
                     }
+                    if self.raw_raft.raft.is_in_membership_change() {
+                        return; // the client retries once the ongoing change is finalized
+                    }
+                    if let ReconfigurationPolicy::SingleStep = self.reconfig_policy {
+                        self.propose_single_step_changes(reconfig.0);
+                        return;
+                    }
                     let mut current_config =
                         self.raw_raft.raft.prs().configuration().voters().clone();
+                    // new nodes always have higher ids than the current ones, so this is a retry of an applied reconfiguration
+                    let max_pid = current_config.iter().max().cloned().unwrap_or(0);
+                    if reconfig.0.iter().all(|pid| *pid <= max_pid) {
+                        self.reply_reconfiguration(leader_pid);
+                        return;
+                    }
                     match self.reconfig_policy {
                         ReconfigurationPolicy::ReplaceLeader => {
                             let mut add_nodes: Vec<u64> = reconfig
@@ -644,7 +1192,7 @@ where
                             current_config.remove(&leader_pid);
                             let mut new_voters = current_config.into_iter().collect::<Vec<u64>>();
                             new_voters.append(&mut add_nodes);
-                            let new_config = (new_voters, vec![]);
+                            let new_config = (new_voters, reconfig.1);
                             // info!(self.ctx.log(), "Joint consensus remove leader: my pid: {}, reconfig: {:?}", leader_pid, new_config);
                             self.raw_raft
                                 .raft
@@ -671,7 +1219,7 @@ where
                                 let mut new_voters =
                                     current_config.into_iter().collect::<Vec<u64>>();
                                 new_voters.append(&mut add_nodes);
-                                let new_config = (new_voters, vec![]);
+                                let new_config = (new_voters, reconfig.1);
                                 // info!(self.ctx.log(), "Joint consensus remove follower: my pid: {}, reconfig: {:?}", leader_pid, new_config);
                                 self.raw_raft
                                     .raft
@@ -681,34 +1229,253 @@ where
                                 self.raw_raft.raft.propose_membership_change(reconfig).expect("Failed to propose joint consensus reconfiguration (remove follower)");
                             }
                         }
+                        ReconfigurationPolicy::PromoteLearners => {
+                            // first add the new nodes as learners next to the current voters
+                            let learners: Vec<u64> = reconfig
+                                .0
+                                .iter()
+                                .chain(reconfig.1.iter())
+                                .filter(|pid| !current_config.contains(*pid))
+                                .cloned()
+                                .collect();
+                            let voters = current_config.into_iter().collect::<Vec<u64>>();
+                            self.raw_raft
+                                .raft
+                                .propose_membership_change((voters, learners))
+                                .expect("Failed to propose joint consensus reconfiguration (add learners)");
+                            self.promotion = Some(reconfig);
+                        }
+                        ReconfigurationPolicy::SingleStep => unreachable!(),
                     }
                     self.reconfig_state = ReconfigurationState::Pending;
                 }
             }
             None => {
                 // i.e normal operation
-                let data = proposal.data;
-                self.raw_raft.propose(vec![], data).unwrap_or_else(|_| {
-                    panic!(
-                        "Failed to propose. leader: {}, lead_transferee: {:?}",
-                        self.raw_raft.raft.leader_id, self.raw_raft.raft.lead_transferee
-                    )
-                });
+                if self.raw_raft.raft.lead_transferee.is_some() {
+                    // the leader drops proposals while handing over, retry them after the transfer
+                    self.hold_proposal(proposal);
+                    return;
+                }
+                if self.batch_size > 1 {
+                    self.batch_proposal(proposal.data);
+                    return;
+                }
+                self.propose_entry(proposal.data);
+            }
+        }
+    }
+
+    fn batch_proposal(&mut self, data: Vec<u8>) {
+        if self.batch.is_empty() {
+            let timer = self.schedule_once(self.batch_timeout, move |c, _| {
+                c.batch_timer = None;
+                c.propose_batch();
+                Handled::Ok
+            });
+            self.batch_timer = Some(timer);
+        }
+        self.batch.extend_from_slice(&data);
+        self.batch_len += command_ids(&data).len();
+        if self.batch_len >= self.batch_size {
+            if let Some(timer) = self.batch_timer.take() {
+                self.cancel_timer(timer);
+            }
+            self.propose_batch();
+        }
+    }
+
+    fn propose_batch(&mut self) {
+        if self.batch.is_empty() {
+            return;
+        }
+        let data = std::mem::take(&mut self.batch);
+        self.batch_len = 0;
+        if self.raw_raft.raft.leader_id == 0 || self.raw_raft.raft.lead_transferee.is_some() {
+            // retried as a whole once there is a leader again
+            self.hold_proposal(Proposal::normal(data));
+            return;
+        }
+        self.propose_entry(data);
+    }
+
+    fn propose_single_step_changes(&mut self, target: Vec<u64>) {
+        let my_pid = self.raw_raft.raft.id;
+        let current_config = self.raw_raft.raft.prs().configuration().voters().clone();
+        let mut changes: Vec<ConfChange> = target
+            .iter()
+            .filter(|pid| !current_config.contains(*pid))
+            .map(|pid| Self::conf_change(ConfChangeType::AddNode, *pid))
+            .collect();
+        // remove the leader last so that it can drive the other changes
+        let mut remove_nodes: Vec<u64> = current_config
+            .iter()
+            .filter(|pid| !target.contains(*pid))
+            .cloned()
+            .collect();
+        remove_nodes.sort_by_key(|pid| *pid == my_pid);
+        changes.extend(
+            remove_nodes
+                .into_iter()
+                .map(|pid| Self::conf_change(ConfChangeType::RemoveNode, pid)),
+        );
+        if changes.is_empty() {
+            // retry of an applied reconfiguration
+            self.reply_reconfiguration(my_pid);
+            return;
+        }
+        self.conf_changes = Some(changes);
+        self.try_propose_next_conf_change();
+        self.reconfig_state = ReconfigurationState::Pending;
+    }
+
+    fn conf_change(change_type: ConfChangeType, pid: u64) -> ConfChange {
+        let mut cc = ConfChange::default();
+        cc.set_change_type(change_type);
+        cc.set_node_id(pid);
+        cc
+    }
+
+    // raft-rs replaces a conf change with an empty entry while the previous one is not applied,
+    // so the next change waits until the Ready that applied it has been advanced
+    fn try_propose_next_conf_change(&mut self) {
+        let has_next = matches!(&self.conf_changes, Some(changes) if !changes.is_empty());
+        if has_next
+            && self.raw_raft.raft.state == StateRole::Leader
+            && !self.raw_raft.raft.has_pending_conf()
+        {
+            self.propose_next_conf_change();
+        }
+    }
+
+    fn propose_next_conf_change(&mut self) {
+        let cc = self
+            .conf_changes
+            .as_mut()
+            .expect("No single-step reconfiguration in progress")
+            .remove(0);
+        debug!(self.ctx.log(), "Proposing conf change: {:?}", cc);
+        self.raw_raft
+            .propose_conf_change(vec![], cc)
+            .expect("Failed to propose conf change");
+    }
+
+    fn reply_reconfiguration(&mut self, leader: u64) {
+        let current_conf = self.raw_raft.raft.prs().configuration();
+        let current_voters = current_conf.voters();
+        let current_learners = current_conf.learners();
+        let conf_len = current_voters.len();
+        let learners_len = current_learners.len();
+        let mut data: Vec<u8> = Vec::with_capacity(8 + 4 + 8 * conf_len + 4 + 8 * learners_len);
+        data.put_u64(RECONFIG_ID);
+        data.put_u32(conf_len as u32);
+        for pid in current_voters {
+            data.put_u64(*pid);
+        }
+        data.put_u32(learners_len as u32);
+        for pid in current_learners {
+            data.put_u64(*pid);
+        }
+        let pr = ProposalResp::with(data, leader);
+        self.communication_port
+            .trigger(CommunicatorMsg::ProposalResponse(self.group, pr));
+    }
+
+    fn try_promote_learners(&mut self) {
+        if self.raw_raft.raft.state != StateRole::Leader
+            || self.raw_raft.raft.is_in_membership_change()
+            || self.reconfig_state != ReconfigurationState::CatchingUp
+        {
+            return;
+        }
+        let committed = self.raw_raft.raft.raft_log.committed;
+        let prs = self.raw_raft.raft.prs();
+        let caught_up = prs
+            .configuration()
+            .learners()
+            .iter()
+            .all(|pid| prs.get(*pid).map_or(false, |pr| pr.matched >= committed));
+        if caught_up {
+            let reconfig = self.promotion.take().unwrap();
+            info!(self.ctx.log(), "Learners caught up, promoting to: {:?}", reconfig);
+            self.raw_raft
+                .raft
+                .propose_membership_change(reconfig)
+                .expect("Failed to propose joint consensus reconfiguration (promote learners)");
+            self.reconfig_state = ReconfigurationState::Pending;
+        }
+    }
+
+    // raft-rs silently drops a ReadIndex at a leader without a committed entry in its term
+    fn committed_in_term(&self) -> bool {
+        let raft_log = &self.raw_raft.raft.raft_log;
+        raft_log
+            .term(raft_log.committed)
+            .map_or(false, |term| term == self.raw_raft.raft.term)
+    }
+
+    fn read(&mut self, id: u64) {
+        let leader = self.raw_raft.raft.leader_id;
+        if leader == 0 || (leader == self.raw_raft.raft.id && !self.committed_in_term()) {
+            self.hold_read(id);
+            return;
+        }
+        // the read id is the request context, raft hands it back with the read index
+        let mut ctx: Vec<u8> = Vec::with_capacity(8);
+        ctx.put_u64(id);
+        self.raw_raft.read_index(ctx);
+    }
+
+    fn answer_reads(&mut self) {
+        let applied = self.applied;
+        let leader = self.raw_raft.raft.leader_id;
+        let pending_reads = std::mem::take(&mut self.pending_reads);
+        for (index, id) in pending_reads {
+            if index <= applied {
+                let rr = ReadResp::with(id, index, leader);
+                self.communication_port
+                    .trigger(CommunicatorMsg::ReadResponse(self.group, rr));
+            } else {
+                self.pending_reads.push((index, id));
             }
         }
     }
 
     fn on_ready(&mut self) -> Handled {
-        if !self.raw_raft.has_ready() {
+        // committed entries of Readys that are still being synced are not handed out again
+        if !self.raw_raft.has_ready_since(Some(self.queued_index)) {
             return Handled::Ok;
         }
         let mut store = self.raw_raft.raft.raft_log.store.clone();
 
-        // Get the `Ready` with `RawNode::ready` interface.
-        let mut ready = self.raw_raft.ready();
+        // Get the `Ready` with `RawNode::ready_since` interface.
+        let mut ready = self.raw_raft.ready_since(self.queued_index);
+
+        // Apply the snapshot. It's necessary because in `RawNode::advance_append` we stabilize the snapshot.
+        // This must happen before appending the entries, as installing a snapshot replaces the log.
+        let mut snapshot_index = None;
+        if *ready.snapshot() != Snapshot::default() {
+            let snapshot = ready.snapshot().clone();
+            if let Err(e) = store.apply_snapshot(snapshot.clone()) {
+                error!(
+                    self.ctx.log(),
+                    "{}",
+                    format!("apply snapshot fail: {:?}, need to retry or panic", e)
+                );
+                return Handled::Ok;
+            }
+            self.restore_snapshot(&snapshot);
+            let index = snapshot.get_metadata().index;
+            // the snapshot already covers the committed entries that wait for a sync
+            for (_, pending) in self.unsynced_readies.iter_mut() {
+                pending.committed_entries.retain(|e| e.index > index);
+            }
+            self.queued_index = index;
+            snapshot_index = Some(index);
+        }
 
-        // Persistent raft logs. It's necessary because in `RawNode::advance` we stabilize
-        // raft logs to the latest position.
+        // Persistent raft logs. It's necessary because in `RawNode::advance_append` we stabilize
+        // raft logs to the latest position. With a log writer this only reaches the page cache.
         if let Err(e) = store.append_log(ready.entries()) {
             error!(
                 self.ctx.log(),
@@ -718,37 +1485,78 @@ where
             return Handled::Ok;
         }
 
-        // Apply the snapshot. It's necessary because in `RawNode::advance` we stabilize the snapshot.
-        if *ready.snapshot() != Snapshot::default() {
-            unimplemented!("Should not be any snapshots to handle!");
+        // Persist the hard state before sending any messages, e.g. a granted vote has to be
+        // stable before the candidate learns about it.
+        if let Some(hs) = ready.hs() {
+            store
+                .save_hard_state(hs.clone())
+                .expect("Failed to save hardstate");
+        }
+        let written = !ready.entries().is_empty() || ready.hs().is_some();
+        let pending = PendingReady {
+            messages: std::mem::take(&mut ready.messages),
+            read_states: ready.read_states().to_vec(),
+            committed_entries: ready.committed_entries.take().unwrap_or_default(),
+        };
+        if let Some(last_committed) = pending.committed_entries.last() {
+            self.queued_index = last_committed.index;
         }
+        // the entries are in storage, so raft can produce the next Ready while this one is synced
+        self.raw_raft.advance_append(ready);
+        if let Some(index) = snapshot_index {
+            self.raw_raft.advance_apply(index);
+        }
+        if self.log_writer.is_some() && written {
+            // Readys written before the log writer syncs are synced together
+            self.sync_seq += 1;
+            let sync = LogWriterMsg::Sync(self.sync_seq, self.actor_ref());
+            self.log_writer.as_ref().unwrap().tell(sync);
+        }
+        if self.synced_seq < self.sync_seq {
+            // also keeps the order behind the Readys that are not synced yet
+            self.unsynced_readies.push((self.sync_seq, pending));
+        } else {
+            self.handle_ready(pending);
+        }
+        Handled::Ok
+    }
+
+    // everything of the Ready that has to wait until its entries and hard state are persisted
+    fn handle_ready(&mut self, pending: PendingReady) {
+        let mut store = self.raw_raft.raft.raft_log.store.clone();
 
         // Send out the messages come from the node.
-        let mut ready_msgs = Vec::with_capacity(self.max_inflight);
-        std::mem::swap(&mut ready.messages, &mut ready_msgs);
-        for msg in ready_msgs {
+        for msg in pending.messages {
             self.communication_port
-                .trigger(CommunicatorMsg::RawRaftMsg(msg));
+                .trigger(CommunicatorMsg::RawRaftMsg(self.group, msg));
+        }
+        // reads are answered once everything up to their read index has been applied
+        for rs in &pending.read_states {
+            let id = rs.request_ctx.as_slice().get_u64();
+            self.pending_reads.push((rs.index, id));
         }
         // let mut next_conf_change: Option<ConfChangeType> = None;
         // Apply all committed proposals.
-        if let Some(committed_entries) = ready.committed_entries.take() {
+        let committed_entries = pending.committed_entries;
+        if !committed_entries.is_empty() {
             for entry in &committed_entries {
                 if entry.data.is_empty() {
                     // From new elected leaders.
                     continue;
                 }
                 if let EntryType::EntryConfChange = entry.get_entry_type() {
-                    if self.reconfig_state == ReconfigurationState::Removed
-                        || self.reconfig_state == ReconfigurationState::Finished
-                    {
+                    if self.reconfig_state == ReconfigurationState::Removed {
                         continue;
                     }
                     // For conf change messages, make them effective.
                     let mut cc = ConfChange::default();
                     cc.merge_from_bytes(&entry.data).unwrap();
                     let change_type = cc.get_change_type();
+                    let in_membership_change = self.raw_raft.raft.is_in_membership_change();
                     match &change_type {
+                        // e.g. a finalize proposed by both the old and a newly elected leader
+                        ConfChangeType::BeginMembershipChange if in_membership_change => continue,
+                        ConfChangeType::FinalizeMembershipChange if !in_membership_change => continue,
                         ConfChangeType::BeginMembershipChange => {
                             let reconfig = cc.get_configuration();
                             let start_index = cc.get_start_index();
@@ -775,11 +1583,19 @@ where
 
                             let current_conf = self.raw_raft.raft.prs().configuration().clone();
                             let current_voters = current_conf.voters();
-                            if !current_voters.contains(&self.raw_raft.raft.id) {
+                            let current_learners = current_conf.learners();
+                            let my_pid = self.raw_raft.raft.id;
+                            if !current_voters.contains(&my_pid) && !current_learners.contains(&my_pid) {
                                 self.stop_timers();
                                 self.reconfig_state = ReconfigurationState::Removed;
+                            } else if !current_learners.is_empty()
+                                && matches!(self.reconfig_policy, ReconfigurationPolicy::PromoteLearners)
+                            {
+                                // the learners still have to be promoted
+                                self.reconfig_state = ReconfigurationState::CatchingUp;
                             } else {
-                                self.reconfig_state = ReconfigurationState::Finished;
+                                // ready for the next reconfiguration
+                                self.reconfig_state = ReconfigurationState::None;
                             }
                             let leader = self.raw_raft.raft.leader_id;
                             if leader == 0 {
@@ -814,41 +1630,167 @@ where
                                     );
                                 }
                             }
-                            let conf_len = current_voters.len();
-                            let mut data: Vec<u8> = Vec::with_capacity(8 + 4 + 8 * conf_len);
-                            data.put_u64(RECONFIG_ID);
-                            data.put_u32(conf_len as u32);
-                            for pid in current_voters {
-                                data.put_u64(*pid);
-                            }
                             let cs = ConfState::from(current_conf);
                             store.set_conf_state(cs, None);
-
-                            let pr = ProposalResp::with(data, leader);
-                            self.communication_port
-                                .trigger(CommunicatorMsg::ProposalResponse(pr));
+                            self.reply_reconfiguration(leader);
+                        }
+                        ConfChangeType::AddNode | ConfChangeType::RemoveNode => {
+                            let cs = self
+                                .raw_raft
+                                .apply_conf_change(&cc)
+                                .expect("Failed to apply conf change");
+                            store.set_conf_state(cs, None);
+                            let my_pid = self.raw_raft.raft.id;
+                            if change_type == ConfChangeType::RemoveNode && cc.get_node_id() == my_pid {
+                                self.stop_timers();
+                                self.reconfig_state = ReconfigurationState::Removed;
+                            }
+                            match self.conf_changes.as_ref().map(|changes| changes.is_empty()) {
+                                Some(true) => {
+                                    self.conf_changes = None;
+                                    let leader = if self.reconfig_state == ReconfigurationState::Removed {
+                                        0 // wait for a leader in the new configuration
+                                    } else {
+                                        self.reconfig_state = ReconfigurationState::None;
+                                        self.raw_raft.raft.leader_id
+                                    };
+                                    self.reply_reconfiguration(leader);
+                                }
+                                Some(false) if self.raw_raft.raft.state == StateRole::Leader => {
+                                    // proposed after advance, see try_propose_next_conf_change
+                                }
+                                Some(false) => {
+                                    // lost leadership, the client retries the remaining changes at the new leader
+                                    self.conf_changes = None;
+                                    self.reconfig_state = ReconfigurationState::None;
+                                }
+                                None => {}
+                            }
                         }
                         _ => unimplemented!(),
                     }
                 } else {
-                    // normal proposals
-                    if self.raw_raft.raft.state == StateRole::Leader {
-                        let pr =
-                            ProposalResp::with(entry.get_data().to_vec(), self.raw_raft.raft.id);
-                        self.communication_port
-                            .trigger(CommunicatorMsg::ProposalResponse(pr));
+                    // normal proposals, every replica applies them and the leader responds
+                    let is_leader = self.raw_raft.raft.state == StateRole::Leader;
+                    for (id, watermark, command) in deserialise_commands(entry.get_data()) {
+                        let response = self.kv_store.apply(id, watermark, &command);
+                        if let (true, Some(response)) = (is_leader, response) {
+                            let mut data: Vec<u8> = Vec::with_capacity(14);
+                            data.put_u64(id);
+                            response.serialise(&mut data);
+                            let pr = ProposalResp::with(data, self.raw_raft.raft.id);
+                            self.communication_port
+                                .trigger(CommunicatorMsg::ProposalResponse(self.group, pr));
+                        }
                     }
                 }
             }
             if let Some(last_committed) = committed_entries.last() {
-                store
-                    .set_hard_state(last_committed.index, last_committed.term)
-                    .expect("Failed to set hardstate");
+                self.applied = last_committed.index;
             }
+            if self.snapshot_interval > 0
+                && self.applied - self.snapshot_index >= self.snapshot_interval
+            {
+                self.create_snapshot();
+            }
+            if self.compaction_interval > 0
+                && self.applied - self.compacted_index >= self.compaction_interval
+            {
+                self.compact_log();
+            }
+        }
+        if !self.pending_reads.is_empty() {
+            self.answer_reads();
+        }
+        // Call `RawNode::advance_apply` interface to update the applied index in the raft.
+        if !committed_entries.is_empty() {
+            self.raw_raft.advance_apply(self.applied);
+        }
+        self.try_propose_next_conf_change();
+    }
+}
+
+#[derive(Debug)]
+pub enum LogWriterMsg {
+    Sync(u64, ActorRef<RaftReplicaMsg>),
+}
+
+// Syncs the log of a RaftReplica on a dedicated thread. The replica appends each Ready to the page
+// cache and produces the next ones while the sync is pending, but holds back their messages and
+// committed entries until it is done. A sync waits for group_commit_period, so the Readys appended
+// meanwhile share it.
+#[derive(ComponentDefinition)]
+pub struct LogWriter<S>
+where
+    S: RaftStorage + Send + Clone + 'static,
+{
+    ctx: ComponentContext<Self>,
+    store: S,
+    group_commit_period: Duration,
+    pending: Option<(u64, ActorRef<RaftReplicaMsg>)>, // latest sync request not synced yet
+    timer: Option<ScheduledTimer>,
+}
+
+impl<S> LogWriter<S>
+where
+    S: RaftStorage + Send + Clone + 'static,
+{
+    pub fn with(store: S, group_commit_period: Duration) -> LogWriter<S> {
+        LogWriter {
+            ctx: ComponentContext::uninitialised(),
+            store,
+            group_commit_period,
+            pending: None,
+            timer: None,
+        }
+    }
+
+    fn sync_pending(&mut self) -> Handled {
+        self.timer = None;
+        if let Some((seq, replica)) = self.pending.take() {
+            self.store.sync().expect("Failed to sync log");
+            replica.tell(RaftReplicaMsg::LogSynced(seq));
+        }
+        Handled::Ok
+    }
+}
+
+impl<S> ComponentLifecycle for LogWriter<S>
+where
+    S: RaftStorage + Send + Clone + 'static,
+{
+    fn on_kill(&mut self) -> Handled {
+        if let Some(timer) = self.timer.take() {
+            self.cancel_timer(timer);
         }
-        // Call `RawNode::advance` interface to update position flags in the raft.
-        self.raw_raft.advance(ready);
         Handled::Ok
     }
 }
 
+impl<S> Actor for LogWriter<S>
+where
+    S: RaftStorage + Send + Clone + 'static,
+{
+    type Message = LogWriterMsg;
+
+    fn receive_local(&mut self, msg: Self::Message) -> Handled {
+        match msg {
+            LogWriterMsg::Sync(seq, replica) => {
+                self.pending = Some((seq, replica));
+                if self.group_commit_period == Duration::from_millis(0) {
+                    return self.sync_pending();
+                } else if self.timer.is_none() {
+                    let timer = self
+                        .schedule_once(self.group_commit_period, move |c, _| c.sync_pending());
+                    self.timer = Some(timer);
+                }
+            }
+        }
+        Handled::Ok
+    }
+
+    fn receive_network(&mut self, _msg: NetMessage) -> Handled {
+        // ignore
+        Handled::Ok
+    }
+}
@@ -0,0 +1,35 @@
+use super::{
+    atomic_broadcast::{Done, SequenceResp},
+    partitioning_actor::{Init, PartitioningActorMsg},
+};
+use kompact::prelude::*;
+
+/// A component replicating a log among the nodes of an experiment.
+///
+/// The master creates one per node and registers it under `PATH`, where the `PartitioningActor`
+/// drives it with Init/Run/Stop. After an iteration, the decided sequence is collected
+/// through `get_sequence` and the component is torn down through `kill`.
+pub trait ReplicatedLog: ComponentDefinition + Sized + 'static {
+    const PATH: &'static str;
+
+    fn create(initial_config: Vec<u64>, reconfig_policy: &str) -> Self;
+
+    fn handle_init(&mut self, init: Init, partitioning_actor: ActorPath) -> Handled;
+
+    fn handle_run(&mut self) -> Handled;
+
+    fn handle_stop(&mut self) -> Handled;
+
+    fn get_sequence(ask: Ask<(), SequenceResp>) -> <Self as ActorRaw>::Message;
+
+    fn kill(ask: Ask<(), Done>) -> <Self as ActorRaw>::Message;
+
+    fn handle_partitioning_msg(&mut self, msg: PartitioningActorMsg, sender: ActorPath) -> Handled {
+        match msg {
+            PartitioningActorMsg::Init(init) => self.handle_init(init, sender),
+            PartitioningActorMsg::Run => self.handle_run(),
+            PartitioningActorMsg::Stop => self.handle_stop(),
+            _ => Handled::Ok,
+        }
+    }
+}
@@ -5,11 +5,16 @@ pub mod raft {
     use protobuf::{parse_from_bytes, Message as PbMessage};
     use std::{
         convert::TryInto,
-        fs::{create_dir_all, remove_dir_all, File, OpenOptions},
-        io::{prelude::*, Error as IOError, ErrorKind::NotFound, SeekFrom, Write},
+        error::Error as StdError,
+        fmt,
+        fs::{
+            create_dir_all, read, read_dir, remove_dir_all, remove_file, rename, File, OpenOptions,
+        },
+        io::{prelude::*, Error as IOError, ErrorKind::NotFound, Write},
         mem::size_of,
         ops::Range,
         path::PathBuf,
+        str::FromStr,
         sync::{Arc, RwLock, RwLockReadGuard, RwLockWriteGuard},
     };
     use tikv_raft::{prelude::*, storage::*, util::limit_size, Error, StorageError};
@@ -22,13 +27,51 @@ pub mod raft {
             pending_membership_change: Option<(ConfState, u64)>,
         );
         fn set_hard_state(&mut self, commit: u64, term: u64) -> Result<(), Error>;
+        fn save_hard_state(&mut self, hs: HardState) -> Result<(), Error>;
         fn new_with_conf_state(dir: Option<&str>, conf_state: (Vec<u64>, Vec<u64>)) -> Self;
+        fn recover(dir: Option<&str>) -> Option<Self>;
         fn clear(&mut self) -> Result<(), IOError>;
+        fn create_snapshot(&mut self, index: u64, data: Vec<u8>) -> Result<Snapshot, Error>;
+        fn apply_snapshot(&mut self, snapshot: Snapshot) -> Result<(), Error>;
+        fn compact(&mut self, compact_index: u64) -> Result<(), Error>;
+        fn set_durability(&mut self, durability: Durability);
+        fn sync(&mut self) -> Result<(), Error>;
+    }
+
+    // how eagerly written state is pushed to disk, set by durability in the raft config
+    #[derive(Clone, Copy, Debug, PartialEq)]
+    pub enum Durability {
+        None,       // left to the OS
+        Flush,      // flushed asynchronously after every append
+        FsyncBatch, // synced once per appended batch of entries
+        FsyncEntry, // synced after every appended entry
+    }
+
+    impl FromStr for Durability {
+        type Err = String;
+
+        fn from_str(s: &str) -> Result<Self, Self::Err> {
+            match s {
+                "none" => Ok(Durability::None),
+                "flush" => Ok(Durability::Flush),
+                "fsync-batch" => Ok(Durability::FsyncBatch),
+                "fsync-entry" => Ok(Durability::FsyncEntry),
+                unknown => Err(format!("Got unknown durability mode: {}", unknown)),
+            }
+        }
+    }
+
+    // tikv's MemStorage builds snapshots from its hard state without any data, so the latest
+    // snapshot is kept here to be sent to followers that fall behind the compacted log
+    #[derive(Clone)]
+    pub struct MemStorage {
+        store: tikv_raft::storage::MemStorage,
+        snapshot: Arc<RwLock<Snapshot>>,
     }
 
     impl RaftStorage for MemStorage {
         fn append_log(&mut self, entries: &[tikv_raft::eraftpb::Entry]) -> Result<(), Error> {
-            self.wl().append(entries)
+            self.store.wl().append(entries)
         }
 
         fn set_conf_state(
@@ -36,32 +79,349 @@ pub mod raft {
             cs: ConfState,
             pending_membership_change: Option<(ConfState, u64)>,
         ) {
-            self.wl().set_conf_state(cs, pending_membership_change);
+            self.store
+                .wl()
+                .set_conf_state(cs, pending_membership_change);
         }
 
         fn set_hard_state(&mut self, commit: u64, term: u64) -> Result<(), Error> {
-            self.wl().mut_hard_state().commit = commit;
-            self.wl().mut_hard_state().term = term;
+            self.store.wl().mut_hard_state().commit = commit;
+            self.store.wl().mut_hard_state().term = term;
+            Ok(())
+        }
+
+        fn save_hard_state(&mut self, hs: HardState) -> Result<(), Error> {
+            *self.store.wl().mut_hard_state() = hs;
             Ok(())
         }
 
         fn new_with_conf_state(_dir: Option<&str>, conf_state: (Vec<u64>, Vec<u64>)) -> Self {
-            MemStorage::new_with_conf_state(conf_state)
+            let store = tikv_raft::storage::MemStorage::new_with_conf_state(conf_state);
+            let snapshot = store.snapshot(0).expect("Failed to get initial snapshot");
+            MemStorage {
+                store,
+                snapshot: Arc::new(RwLock::new(snapshot)),
+            }
+        }
+
+        fn recover(_dir: Option<&str>) -> Option<Self> {
+            None
         }
 
         fn clear(&mut self) -> Result<(), IOError> {
             Ok(())
         }
+
+        fn create_snapshot(&mut self, index: u64, data: Vec<u8>) -> Result<Snapshot, Error> {
+            if index <= self.snapshot.read().unwrap().get_metadata().index {
+                return Err(Error::Store(StorageError::SnapshotOutOfDate));
+            }
+            let last_index = self.last_index()?;
+            if index > last_index {
+                panic!(
+                    "snapshot index out of bound (last: {}, snapshot: {})",
+                    last_index, index
+                );
+            }
+            let term = self.term(index)?;
+            let raft_state = self.store.initial_state()?;
+            let mut snapshot = Snapshot::default();
+            snapshot.set_data(data);
+            let meta = snapshot.mut_metadata();
+            meta.index = index;
+            meta.term = term;
+            meta.set_conf_state(raft_state.conf_state);
+            if let Some(cs) = raft_state.pending_conf_state {
+                meta.set_pending_membership_change(cs);
+                meta.pending_membership_change_index = raft_state
+                    .pending_conf_state_start_index
+                    .expect("Pending conf state without start index");
+            }
+            *self.snapshot.write().unwrap() = snapshot.clone();
+            Ok(snapshot)
+        }
+
+        fn apply_snapshot(&mut self, snapshot: Snapshot) -> Result<(), Error> {
+            self.store.wl().apply_snapshot(snapshot.clone())?;
+            *self.snapshot.write().unwrap() = snapshot;
+            Ok(())
+        }
+
+        fn compact(&mut self, compact_index: u64) -> Result<(), Error> {
+            // lagging followers can only catch up on compacted entries through the snapshot
+            let snapshot_index = self.snapshot.read().unwrap().get_metadata().index;
+            if compact_index > snapshot_index + 1 {
+                panic!(
+                    "compact beyond the latest snapshot: {}, snapshot index: {}",
+                    compact_index, snapshot_index
+                );
+            }
+            self.store.wl().compact(compact_index)
+        }
+
+        fn set_durability(&mut self, _durability: Durability) {}
+
+        fn sync(&mut self) -> Result<(), Error> {
+            Ok(())
+        }
+    }
+
+    impl Storage for MemStorage {
+        fn initial_state(&self) -> Result<RaftState, Error> {
+            self.store.initial_state()
+        }
+
+        fn entries(
+            &self,
+            low: u64,
+            high: u64,
+            max_size: impl Into<Option<u64>>,
+        ) -> Result<Vec<Entry>, Error> {
+            self.store.entries(low, high, max_size)
+        }
+
+        fn term(&self, idx: u64) -> Result<u64, Error> {
+            // the compacted log no longer holds the entry of the snapshot
+            let snapshot = self.snapshot.read().unwrap();
+            if idx == snapshot.get_metadata().index {
+                return Ok(snapshot.get_metadata().term);
+            }
+            self.store.term(idx)
+        }
+
+        fn first_index(&self) -> Result<u64, Error> {
+            self.store.first_index()
+        }
+
+        fn last_index(&self) -> Result<u64, Error> {
+            self.store.last_index()
+        }
+
+        fn snapshot(&self, request_index: u64) -> Result<Snapshot, Error> {
+            let snapshot = self.snapshot.read().unwrap();
+            if snapshot.get_metadata().index < request_index {
+                // no snapshot has been created that far yet, raft will retry later
+                return Err(Error::Store(StorageError::SnapshotTemporarilyUnavailable));
+            }
+            Ok(snapshot.clone())
+        }
+    }
+
+    // a log record that cannot be read back, identified by the raft index of its entry
+    #[derive(Debug, PartialEq)]
+    pub enum LogCorruption {
+        TornRecord(u64),       // the record does not fit in its segment
+        ChecksumMismatch(u64), // the stored checksum does not match the entry
     }
 
-    struct FileMmap {
-        file: File,
-        mem_map: MmapMut,
+    impl fmt::Display for LogCorruption {
+        fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+            match self {
+                LogCorruption::TornRecord(index) => write!(f, "torn log record at index {}", index),
+                LogCorruption::ChecksumMismatch(index) => {
+                    write!(f, "checksum mismatch of log record at index {}", index)
+                }
+            }
+        }
+    }
+
+    impl StdError for LogCorruption {}
+
+    // A part of the log holding consecutive entries, starting at raft index first_index. Each
+    // segment has its own log file and an offset file that maps from the position of an entry in
+    // the segment to its byte offset in the log file.
+    struct LogSegment {
+        first_index: u64,
+        num_entries: u64,
+        end: usize, // byte offset in log right after the last entry
+        log: MmapMut,
+        offset: MmapMut,
+        files: (File, File), // log and offset file, kept to sync them without holding the memory maps
     }
 
-    impl FileMmap {
-        fn new(file: File, mem_map: MmapMut) -> FileMmap {
-            FileMmap { file, mem_map }
+    impl LogSegment {
+        const SEGMENT_SIZE: u64 = 8388608; // LogCabin also uses 8MB files...
+        const RECORD_HEADER: usize = 12; // u64 length and u32 crc of the serialised entry
+
+        fn path(dir: &str, first_index: u64, ext: &str) -> PathBuf {
+            PathBuf::from(format!("{}/segment_{}.{}", dir, first_index, ext))
+        }
+
+        fn create(dir: &str, first_index: u64, size: u64) -> LogSegment {
+            let (log_file, log) =
+                LogSegment::map_file(&LogSegment::path(dir, first_index, "log"), Some(size));
+            // every entry takes up at least the 8 bytes of its length, so the offsets always fit
+            let (offset_file, offset) =
+                LogSegment::map_file(&LogSegment::path(dir, first_index, "offset"), Some(size));
+            LogSegment {
+                first_index,
+                num_entries: 0,
+                end: 0,
+                log,
+                offset,
+                files: (log_file, offset_file),
+            }
+        }
+
+        // the files are preallocated, so the entries have to be restored with recover
+        fn open(dir: &str, first_index: u64) -> LogSegment {
+            let (log_file, log) =
+                LogSegment::map_file(&LogSegment::path(dir, first_index, "log"), None);
+            let (offset_file, offset) =
+                LogSegment::map_file(&LogSegment::path(dir, first_index, "offset"), None);
+            LogSegment {
+                first_index,
+                num_entries: 0,
+                end: 0,
+                log,
+                offset,
+                files: (log_file, offset_file),
+            }
+        }
+
+        // Restores up to num_entries entries and returns how many of them are intact. Entries
+        // before position check_from are compacted and not checked.
+        fn recover(&mut self, num_entries: u64, check_from: u64) -> u64 {
+            for i in 0..num_entries {
+                let record = if i < check_from {
+                    self.get_record(i)
+                } else {
+                    self.check_record(i).ok()
+                };
+                match record {
+                    Some(r) => {
+                        self.num_entries = i + 1;
+                        self.end = r.end;
+                    }
+                    None => break,
+                }
+            }
+            self.num_entries
+        }
+
+        fn map_file(path: &PathBuf, size: Option<u64>) -> (File, MmapMut) {
+            let file = OpenOptions::new()
+                .read(true)
+                .write(true)
+                .create(size.is_some())
+                .truncate(size.is_some())
+                .open(path)
+                .unwrap_or_else(|_| panic!("Failed to create/open segment file {:?}", path));
+            if let Some(size) = size {
+                file.set_len(size)
+                    .expect("Failed to set file length of segment");
+            }
+            let mem_map = unsafe {
+                MmapMut::map_mut(&file).expect("Failed to create memory map for segment")
+            };
+            (file, mem_map)
+        }
+
+        fn remove_files(dir: &str, first_index: u64) -> Result<(), IOError> {
+            remove_file(LogSegment::path(dir, first_index, "log"))?;
+            remove_file(LogSegment::path(dir, first_index, "offset"))
+        }
+
+        fn has_space(&self, ser_entry_len: usize) -> bool {
+            self.end + LogSegment::RECORD_HEADER + ser_entry_len <= self.log.len()
+        }
+
+        fn append(&mut self, ser_entry: &[u8]) {
+            let start = self.end;
+            let crc_start = start + size_of::<u64>();
+            let stop = start + LogSegment::RECORD_HEADER;
+            let ser_len = (ser_entry.len() as u64).to_be_bytes();
+            (&mut self.log[start..crc_start])
+                .write_all(&ser_len)
+                .expect("Failed to write to log"); // write len of serialised entry
+            (&mut self.log[crc_start..stop])
+                .write_all(&crc32fast::hash(ser_entry).to_be_bytes())
+                .expect("Failed to write to log");
+            (&mut self.log[stop..stop + ser_entry.len()])
+                .write_all(ser_entry)
+                .expect("Failed to write to log");
+            self.set_offset(self.num_entries, start as u64);
+            self.num_entries += 1;
+            self.end = stop + ser_entry.len();
+        }
+
+        // writes back the entries from position i and onwards
+        fn flush(&self, i: u64, sync: bool) -> Result<(), IOError> {
+            let log_start = self.get_offset(i);
+            let log_len = self.end - log_start;
+            let offset_start = (i as usize) * size_of::<u64>();
+            let offset_len = ((self.num_entries - i) as usize) * size_of::<u64>();
+            if sync {
+                self.log.flush_range(log_start, log_len)?;
+                self.offset.flush_range(offset_start, offset_len)
+            } else {
+                self.log.flush_async_range(log_start, log_len)?;
+                self.offset.flush_async_range(offset_start, offset_len)
+            }
+        }
+
+        // keeps the first num_entries entries, the rest are overwritten by later appends
+        fn truncate(&mut self, num_entries: u64) {
+            self.end = if num_entries > 0 {
+                self.get_record(num_entries - 1)
+                    .expect("Failed to locate log record")
+                    .end
+            } else {
+                0
+            };
+            self.num_entries = num_entries;
+        }
+
+        // returns byte offset in log from the position of an entry in the segment
+        fn get_offset(&self, i: u64) -> usize {
+            let s = size_of::<u64>();
+            let start = (i as usize) * s;
+            let stop = start + s;
+            let bytes = self.offset.get(start..stop);
+            let offset = u64::from_be_bytes(bytes.unwrap().try_into().unwrap());
+            offset as usize
+        }
+
+        fn set_offset(&mut self, i: u64, offset: u64) {
+            let start = (i as usize) * size_of::<u64>();
+            let stop = start + size_of::<u64>();
+            (&mut self.offset[start..stop])
+                .write_all(&offset.to_be_bytes())
+                .expect("Failed to write to offset");
+        }
+
+        // returns the byte range of the serialised entry at position i in the segment
+        // None if the record does not fit in the segment
+        fn get_record(&self, i: u64) -> Option<Range<usize>> {
+            let start = self.get_offset(i);
+            let entry_len = self.log.get(start..start.checked_add(size_of::<u64>())?)?;
+            let des_entry_len = u64::from_be_bytes(entry_len.try_into().unwrap()) as usize;
+            let data_start = start.checked_add(LogSegment::RECORD_HEADER)?;
+            let stop = data_start.checked_add(des_entry_len)?;
+            if stop > self.log.len() {
+                None
+            } else {
+                Some(data_start..stop)
+            }
+        }
+
+        // returns the byte range of the serialised entry after verifying its checksum
+        fn check_record(&self, i: u64) -> Result<Range<usize>, LogCorruption> {
+            let index = self.first_index + i;
+            let r = self.get_record(i).ok_or(LogCorruption::TornRecord(index))?;
+            let crc_start = r.start - size_of::<u32>();
+            let crc_bytes = &self.log[crc_start..r.start];
+            let crc = u32::from_be_bytes(crc_bytes.try_into().unwrap());
+            if crc != crc32fast::hash(&self.log[r.clone()]) {
+                return Err(LogCorruption::ChecksumMismatch(index));
+            }
+            Ok(r)
+        }
+
+        fn get_entry(&self, index: u64) -> Result<Entry, LogCorruption> {
+            let r = self.check_record(index - self.first_index)?;
+            Ok(parse_from_bytes::<Entry>(&self.log[r])
+                .expect("Protobuf failed to deserialise entry"))
         }
     }
 
@@ -95,6 +455,12 @@ pub mod raft {
             )));
             DiskStorage { core }
         }
+
+        // Reopens a storage from the files persisted in dir, e.g. after a process restart.
+        pub fn open(dir: &str) -> DiskStorage {
+            let core = Arc::new(RwLock::new(DiskStorageCore::open(dir)));
+            DiskStorage { core }
+        }
     }
 
     impl RaftStorage for DiskStorage {
@@ -114,13 +480,51 @@ pub mod raft {
             self.wl().set_hard_state(commit, term)
         }
 
+        fn save_hard_state(&mut self, hs: HardState) -> Result<(), Error> {
+            self.wl().save_hard_state(hs)
+        }
+
         fn new_with_conf_state(dir: Option<&str>, conf_state: (Vec<u64>, Vec<u64>)) -> Self {
             DiskStorage::new_with_conf_state(dir.expect("No DiskStorage path provided"), conf_state)
         }
 
+        fn recover(dir: Option<&str>) -> Option<Self> {
+            let dir = dir.expect("No DiskStorage path provided");
+            if PathBuf::from(format!("{}/hard_state", dir)).exists() {
+                Some(DiskStorage::open(dir))
+            } else {
+                None
+            }
+        }
+
         fn clear(&mut self) -> Result<(), IOError> {
             self.wl().clear_dir()
         }
+
+        fn create_snapshot(&mut self, index: u64, data: Vec<u8>) -> Result<Snapshot, Error> {
+            self.wl().create_snapshot(index, data)
+        }
+
+        fn apply_snapshot(&mut self, snapshot: Snapshot) -> Result<(), Error> {
+            self.wl().apply_snapshot(snapshot)
+        }
+
+        fn compact(&mut self, compact_index: u64) -> Result<(), Error> {
+            self.wl().compact(compact_index)
+        }
+
+        fn set_durability(&mut self, durability: Durability) {
+            self.wl().durability = durability;
+        }
+
+        fn sync(&mut self) -> Result<(), Error> {
+            // only the file handles are taken under the lock, appends continue while syncing
+            let files = self.wl().take_unsynced_files()?;
+            for file in files {
+                file.sync_data()?;
+            }
+            Ok(())
+        }
     }
 
     impl Storage for DiskStorage {
@@ -149,8 +553,8 @@ pub mod raft {
             self.rl().last_index()
         }
 
-        fn snapshot(&self, _request_index: u64) -> Result<Snapshot, Error> {
-            unimplemented!()
+        fn snapshot(&self, request_index: u64) -> Result<Snapshot, Error> {
+            self.rl().snapshot(request_index)
         }
     }
 
@@ -160,12 +564,12 @@ pub mod raft {
         pending_conf_state_start_index: Option<u64>,
         conf_state: ConfState,
         hard_state: MmapMut,
-        log: FileMmap,
-        offset: FileMmap,       // file that maps from index to byte offset
+        segments: Vec<LogSegment>, // ordered by first index, created on demand
         raft_metadata: MmapMut, // memory map with metadata of raft index of first and last entry in log
-        // TODO: Persist these as well?
-        num_entries: u64,
-        snapshot_metadata: SnapshotMetadata,
+        snapshot: Snapshot,     // latest snapshot, persisted in the snapshot file
+        durability: Durability,
+        unsynced_index: Option<u64>, // first index appended since the last sync
+        files: (File, File),         // hard_state and raft_metadata file
     }
 
     impl DiskStorageCore {
@@ -178,87 +582,147 @@ pub mod raft {
         const LAST_INDEX_IS_SET: Range<usize> = 9..10; // 1 if last_index is set
         const LAST_INDEX: Range<usize> = 10..18;
 
-        const FILE_SIZE: u64 = 20971520;
-
         fn new(dir: &str) -> DiskStorageCore {
+            DiskStorageCore::open_files(dir, true)
+        }
+
+        // Rebuilds the storage from existing files. The entries of each segment are derived from
+        // raft_metadata and the first index of the following segment.
+        fn open(dir: &str) -> DiskStorageCore {
+            let mut store = DiskStorageCore::open_files(dir, false);
+            if let Some(snapshot) = store.read_persisted::<Snapshot>("snapshot") {
+                store.snapshot = snapshot;
+            }
+            if let Some(meta) = store.read_persisted::<SnapshotMetadata>("conf_state") {
+                store.conf_state = meta.get_conf_state().clone();
+                if meta.pending_membership_change_index > 0 {
+                    store.pending_conf_state = Some(meta.get_pending_membership_change().clone());
+                    store.pending_conf_state_start_index =
+                        Some(meta.pending_membership_change_index);
+                }
+            }
+            let first_index = store.first_index().expect("Failed to get first index");
+            let last_index = store.last_index().expect("Failed to get last index");
+            // segments are named after the raft index of their first entry
+            let mut segment_indices: Vec<u64> = read_dir(dir)
+                .expect("Failed to read storage directory")
+                .filter_map(|e| {
+                    let name = e.ok()?.file_name().into_string().ok()?;
+                    name.strip_prefix("segment_")?
+                        .strip_suffix(".log")?
+                        .parse()
+                        .ok()
+                })
+                .collect();
+            segment_indices.sort_unstable();
+            let mut valid_last_index = last_index;
+            for (i, seg_first_index) in segment_indices.iter().enumerate() {
+                let next_first_index = match segment_indices.get(i + 1) {
+                    Some(next) => std::cmp::min(*next, valid_last_index + 1),
+                    None => valid_last_index + 1,
+                };
+                if next_first_index <= first_index || next_first_index <= *seg_first_index {
+                    // left behind by an interrupted compaction or overwrite, or after a corrupted entry
+                    LogSegment::remove_files(dir, *seg_first_index)
+                        .expect("Failed to remove stale segment");
+                    continue;
+                }
+                let mut segment = LogSegment::open(dir, *seg_first_index);
+                let num_entries = next_first_index - seg_first_index;
+                let check_from = first_index.saturating_sub(*seg_first_index);
+                let num_valid = segment.recover(num_entries, check_from);
+                if num_valid < num_entries {
+                    // a torn or corrupted entry, the log is truncated back to the entry before it
+                    valid_last_index = seg_first_index + num_valid - 1;
+                }
+                if num_valid == 0 {
+                    LogSegment::remove_files(dir, *seg_first_index)
+                        .expect("Failed to remove corrupted segment");
+                    continue;
+                }
+                store.segments.push(segment);
+            }
+            if valid_last_index < last_index {
+                store
+                    .set_raft_metadata(DiskStorageCore::LAST_INDEX, valid_last_index)
+                    .expect("Failed to set last index metadata");
+                // entries after the truncated ones are fetched from the leader again
+                let commit = store.read_hard_state_field(DiskStorageCore::COMMIT_INDEX);
+                if commit > valid_last_index {
+                    let term = store.read_hard_state_field(DiskStorageCore::TERM_INDEX);
+                    store
+                        .set_hard_state(valid_last_index, term)
+                        .expect("Failed to set hard state");
+                }
+            }
+            store
+        }
+
+        fn open_files(dir: &str, truncate: bool) -> DiskStorageCore {
             create_dir_all(dir)
                 .unwrap_or_else(|_| panic!("Failed to create given directory: {}", dir));
-
-            let log_path = PathBuf::from(format!("{}/log", dir));
-            let log_file = OpenOptions::new()
-                .read(true)
-                .write(true)
-                .create(true)
-                .truncate(true)
-                .open(&log_path)
-                .expect("Failed to create/open log file");
-            log_file
-                .set_len(DiskStorageCore::FILE_SIZE)
-                .expect("Failed to set file length of log"); // LogCabin also uses 8MB files...
-            let log_mmap = unsafe {
-                MmapMut::map_mut(&log_file).expect("Failed to create memory map for log")
-            };
-            let log = FileMmap::new(log_file, log_mmap);
+            if truncate {
+                // segments of a previous storage in the same directory
+                for entry in read_dir(dir).expect("Failed to read storage directory") {
+                    let path = entry.expect("Failed to read storage directory").path();
+                    let is_segment = path
+                        .file_name()
+                        .and_then(|name| name.to_str())
+                        .map_or(false, |name| name.starts_with("segment_"));
+                    if is_segment {
+                        remove_file(&path).expect("Failed to remove old segment");
+                    }
+                }
+            }
 
             let hs_path = PathBuf::from(format!("{}/hard_state", dir));
             let hs_file = OpenOptions::new()
                 .read(true)
                 .write(true)
                 .create(true)
-                .truncate(true)
+                .truncate(truncate)
                 .open(&hs_path)
                 .expect("Failed to create/open hard_state file");
-            hs_file
-                .set_len(3 * size_of::<u64>() as u64)
-                .expect("Failed to set file length of hard_state"); // we only need 3 u64: term, vote and commit
+            if truncate {
+                hs_file
+                    .set_len(3 * size_of::<u64>() as u64)
+                    .expect("Failed to set file length of hard_state"); // we only need 3 u64: term, vote and commit
+            }
             let hard_state = unsafe {
                 MmapMut::map_mut(&hs_file).expect("Failed to create memory map for hard_state")
             };
 
-            let offset_path = PathBuf::from(format!("{}/offset", dir));
-            let offset_file = OpenOptions::new()
-                .read(true)
-                .write(true)
-                .create(true)
-                .truncate(true)
-                .open(&offset_path)
-                .expect("Failed to create/open offset file");
-            offset_file
-                .set_len(DiskStorageCore::FILE_SIZE)
-                .expect("Failed to set file length of offset");
-            let offset_mmap = unsafe {
-                MmapMut::map_mut(&offset_file).expect("Failed to create memory map for offset")
-            };
-            let offset = FileMmap::new(offset_file, offset_mmap);
-
             let raft_metadata_path = PathBuf::from(format!("{}/raft_metadata", dir));
             let raft_metadata_file = OpenOptions::new()
                 .read(true)
                 .write(true)
                 .create(true)
-                .truncate(true)
+                .truncate(truncate)
                 .open(&raft_metadata_path)
                 .expect("Failed to create/open raft_metadata file");
-            raft_metadata_file
-                .set_len((2 * size_of::<u64>() + 2) as u64)
-                .expect("Failed to set file length of raft_file"); // we need 2 u64: first and last index and 2 bytes to check if they are set
+            if truncate {
+                raft_metadata_file
+                    .set_len((2 * size_of::<u64>() + 2) as u64)
+                    .expect("Failed to set file length of raft_file"); // we need 2 u64: first and last index and 2 bytes to check if they are set
+            }
             let raft_metadata = unsafe {
                 MmapMut::map_mut(&raft_metadata_file)
                     .expect("Failed to create memory map for raft_metadata")
             };
 
             let conf_state = ConfState::new();
-            let snapshot_metadata: SnapshotMetadata = Default::default();
+            let snapshot = Snapshot::default();
 
             DiskStorageCore {
                 dir: String::from(dir),
                 conf_state,
                 hard_state,
-                log,
-                offset,
+                segments: Vec::new(),
                 raft_metadata,
-                num_entries: 0,
-                snapshot_metadata,
+                snapshot,
+                durability: Durability::None,
+                unsynced_index: None,
+                files: (hs_file, raft_metadata_file),
                 pending_conf_state: None,
                 pending_conf_state_start_index: None,
             }
@@ -270,8 +734,10 @@ pub mod raft {
         {
             let mut store = DiskStorageCore::new(dir);
             store.conf_state = ConfState::from(conf_state);
-            store.snapshot_metadata.index = 1;
-            store.snapshot_metadata.term = 1;
+            let meta = store.snapshot.mut_metadata();
+            meta.index = 1;
+            meta.term = 1;
+            meta.set_conf_state(store.conf_state.clone());
             (&mut store.hard_state[DiskStorageCore::TERM_INDEX])
                 .write_all(&1u64.to_be_bytes())
                 .expect("Failed to write hard state term");
@@ -279,6 +745,12 @@ pub mod raft {
                 .write_all(&1u64.to_be_bytes())
                 .expect("Failed to write hard state commit");
             store
+                .persist_snapshot(&store.snapshot)
+                .expect("Failed to persist initial snapshot");
+            store
+                .persist_conf_state()
+                .expect("Failed to persist conf state");
+            store
         }
 
         fn read_hard_state_field(&self, field: Range<usize>) -> u64 {
@@ -300,80 +772,88 @@ pub mod raft {
             hs
         }
 
-        fn append_entries(&mut self, entries: &[Entry], from_log_index: u64) -> Result<(), Error> {
-            // appends all entries starting from a given log index
-            let from = if from_log_index >= self.num_entries {
-                SeekFrom::Current(0)
-            } else {
-                SeekFrom::Start(self.get_log_offset(from_log_index) as u64)
-            };
-            self.log.file.seek(from)?;
-            let num_added_entries = entries.len() as u64;
+        fn append_entries(&mut self, entries: &[Entry]) -> Result<(), Error> {
+            // appends all entries, overwriting any existing entries from the first given index
             let new_first_index = entries[0].index;
             let new_last_index = entries.last().unwrap().index;
-            for (i, e) in entries.iter().enumerate() {
-                let current_offset = self
-                    .log
-                    .file
-                    .seek(SeekFrom::Current(0))
-                    .expect("Failed to get current seeked offset"); // byte offset in log file
+            let is_first = new_first_index == self.first_index()?;
+            if new_first_index <= self.last_index()? {
+                self.truncate_segments(new_first_index)?;
+            }
+            self.unsynced_index = Some(match self.unsynced_index {
+                Some(index) => std::cmp::min(index, new_first_index),
+                None => new_first_index,
+            });
+            for e in entries {
                 let ser_entry = e
                     .write_to_bytes()
                     .expect("Protobuf failed to serialise Entry");
-                let ser_entry_len = ser_entry.len() as u64;
-                let ser_len = ser_entry_len.to_be_bytes();
-                self.log.file.write_all(&ser_len)?; // write len of serialised entry
-                match self.log.file.write_all(&ser_entry) {
-                    // write entry
-                    Ok(_) => {
-                        // write to offset file
-                        let start = (from_log_index as usize + i) * size_of::<u64>();
-                        let stop = start + size_of::<u64>();
-                        (&mut self.offset.mem_map[start..stop])
-                            .write_all(&current_offset.to_be_bytes())
-                            .expect("Failed to write to offset");
-                    }
-                    _ => panic!("Failed to write to log"),
+                let is_full = match self.segments.last() {
+                    Some(segment) => !segment.has_space(ser_entry.len()),
+                    None => true,
+                };
+                if is_full {
+                    // entries larger than a segment get a segment of their own
+                    let size = std::cmp::max(
+                        LogSegment::SEGMENT_SIZE,
+                        (LogSegment::RECORD_HEADER + ser_entry.len()) as u64,
+                    );
+                    self.segments
+                        .push(LogSegment::create(&self.dir, e.index, size));
+                }
+                let segment = self.segments.last_mut().unwrap();
+                segment.append(&ser_entry);
+                if self.durability == Durability::FsyncEntry {
+                    segment.flush(segment.num_entries - 1, true)?;
+                }
+            }
+            if self.durability == Durability::Flush || self.durability == Durability::FsyncBatch {
+                let sync = self.durability == Durability::FsyncBatch;
+                let pos = self.segment_position(new_first_index);
+                for segment in &self.segments[pos..] {
+                    let from = new_first_index.saturating_sub(segment.first_index);
+                    segment.flush(from, sync)?;
                 }
             }
-            if from_log_index == 0 {
+            if is_first {
                 self.set_raft_metadata(DiskStorageCore::FIRST_INDEX, new_first_index)
                     .expect("Failed to set first index metadata");
             }
-            let num_removed_entries = self.num_entries - from_log_index;
-            self.num_entries = self.num_entries + num_added_entries - num_removed_entries;
             self.set_raft_metadata(DiskStorageCore::LAST_INDEX, new_last_index)
                 .expect("Failed to set last index metadata");
-            self.log.file.flush()?;
-            self.offset.file.flush()?;
-            //        println!("N: {}, last_index: {}", self.num_entries, new_last_index);
             Ok(())
         }
 
-        // returns byte offset in log from log_index
-        fn get_log_offset(&self, log_index: u64) -> usize {
-            let s = size_of::<u64>();
-            let start = (log_index as usize) * s;
-            let stop = start + s;
-            let bytes = self.offset.mem_map.get(start..stop);
-            let offset = u64::from_be_bytes(bytes.unwrap().try_into().unwrap());
-            offset as usize
+        // removes all entries from index and onwards
+        fn truncate_segments(&mut self, index: u64) -> Result<(), Error> {
+            let pos = self.segment_position(index);
+            let segment = &mut self.segments[pos];
+            let num_kept = index - segment.first_index;
+            segment.truncate(num_kept);
+            // a segment without any remaining entries is removed as well
+            let first_removed = if num_kept == 0 { pos } else { pos + 1 };
+            for segment in self.segments.drain(first_removed..) {
+                LogSegment::remove_files(&self.dir, segment.first_index)?;
+            }
+            Ok(())
         }
 
-        fn get_entry(&self, index: u64) -> Entry {
-            let start = self.get_log_offset(index);
-            let stop = start + size_of::<u64>();
-            let entry_len = self.log.mem_map.get(start..stop);
-            let des_entry_len = u64::from_be_bytes(entry_len.unwrap().try_into().unwrap());
-            let r = stop..(stop + des_entry_len as usize);
-            let entry = self.log.mem_map.get(r).unwrap_or_else(|| {
-                panic!(
-                    "Failed to get serialised entry in range {}..{}",
-                    stop,
-                    stop + des_entry_len as usize
-                )
-            });
-            parse_from_bytes::<Entry>(entry).expect("Protobuf failed to deserialise entry")
+        // returns the position of the segment holding the entry with the given raft index
+        fn segment_position(&self, index: u64) -> usize {
+            match self
+                .segments
+                .binary_search_by_key(&index, |segment| segment.first_index)
+            {
+                Ok(pos) => pos,
+                Err(0) => panic!("No segment holds entry with index {}", index),
+                Err(pos) => pos - 1,
+            }
+        }
+
+        fn get_entry(&self, index: u64) -> Result<Entry, Error> {
+            self.segments[self.segment_position(index)]
+                .get_entry(index)
+                .map_err(|e| Error::Store(StorageError::Other(Box::new(e))))
         }
 
         fn set_raft_metadata(&mut self, field: Range<usize>, value: u64) -> Result<(), Error> {
@@ -394,7 +874,7 @@ pub mod raft {
             (&mut self.raft_metadata[field])
                 .write_all(&value.to_be_bytes())
                 .expect("Failed to write raft metadata");
-            self.raft_metadata.flush()?;
+            self.flush_mmap(&self.raft_metadata)?;
             Ok(())
         }
 
@@ -453,8 +933,7 @@ pub mod raft {
                     last_index, entries[0].index,
                 );
             }
-            let diff = entries[0].index - first_index;
-            self.append_entries(entries, diff)
+            self.append_entries(entries)
         }
 
         fn set_conf_state(
@@ -467,15 +946,189 @@ pub mod raft {
                 self.pending_conf_state = Some(cs);
                 self.pending_conf_state_start_index = Some(idx);
             }
+            self.persist_conf_state()
+                .expect("Failed to persist conf state");
         }
 
         fn set_hard_state(&mut self, commit: u64, term: u64) -> Result<(), Error> {
             (&mut self.hard_state[DiskStorageCore::TERM_INDEX]).write_all(&term.to_be_bytes())?;
             (&mut self.hard_state[DiskStorageCore::COMMIT_INDEX])
                 .write_all(&commit.to_be_bytes())?;
+            self.flush_mmap(&self.hard_state)?;
             Ok(())
         }
 
+        fn save_hard_state(&mut self, hs: HardState) -> Result<(), Error> {
+            (&mut self.hard_state[DiskStorageCore::TERM_INDEX])
+                .write_all(&hs.term.to_be_bytes())?;
+            (&mut self.hard_state[DiskStorageCore::VOTE_INDEX])
+                .write_all(&hs.vote.to_be_bytes())?;
+            (&mut self.hard_state[DiskStorageCore::COMMIT_INDEX])
+                .write_all(&hs.commit.to_be_bytes())?;
+            self.flush_mmap(&self.hard_state)?;
+            Ok(())
+        }
+
+        // handles to the files written since the last call, the log before the metadata
+        fn take_unsynced_files(&mut self) -> Result<Vec<File>, IOError> {
+            let mut files = Vec::new();
+            if let Some(index) = self.unsynced_index.take() {
+                for segment in &self.segments {
+                    if segment.first_index + segment.num_entries > index {
+                        files.push(segment.files.0.try_clone()?);
+                        files.push(segment.files.1.try_clone()?);
+                    }
+                }
+            }
+            files.push(self.files.1.try_clone()?);
+            files.push(self.files.0.try_clone()?);
+            Ok(files)
+        }
+
+        fn flush_mmap(&self, mmap: &MmapMut) -> Result<(), IOError> {
+            match self.durability {
+                Durability::None => Ok(()),
+                Durability::Flush => mmap.flush_async(),
+                Durability::FsyncBatch | Durability::FsyncEntry => mmap.flush(),
+            }
+        }
+
+        fn create_snapshot(&mut self, index: u64, data: Vec<u8>) -> Result<Snapshot, Error> {
+            if index <= self.snapshot.get_metadata().index {
+                return Err(Error::Store(StorageError::SnapshotOutOfDate));
+            }
+            let last_index = self.last_index()?;
+            if index > last_index {
+                panic!(
+                    "snapshot index out of bound (last: {}, snapshot: {})",
+                    last_index, index
+                );
+            }
+            let term = self.term(index)?;
+            let mut snapshot = Snapshot::default();
+            snapshot.set_data(data);
+            let meta = snapshot.mut_metadata();
+            meta.index = index;
+            meta.term = term;
+            self.set_conf_metadata(meta);
+            self.persist_snapshot(&snapshot)?;
+            self.snapshot = snapshot.clone();
+            Ok(snapshot)
+        }
+
+        fn apply_snapshot(&mut self, snapshot: Snapshot) -> Result<(), Error> {
+            let meta = snapshot.get_metadata();
+            let index = meta.index;
+            if self.first_index()? > index {
+                return Err(Error::Store(StorageError::SnapshotOutOfDate));
+            }
+            self.persist_snapshot(&snapshot)?;
+            self.conf_state = meta.get_conf_state().clone();
+            if meta.pending_membership_change_index > 0 {
+                self.pending_conf_state = Some(meta.get_pending_membership_change().clone());
+                self.pending_conf_state_start_index = Some(meta.pending_membership_change_index);
+            } else {
+                self.pending_conf_state = None;
+                self.pending_conf_state_start_index = None;
+            }
+            self.persist_conf_state()?;
+            let term = std::cmp::max(
+                self.read_hard_state_field(DiskStorageCore::TERM_INDEX),
+                meta.term,
+            );
+            self.set_hard_state(index, term)?;
+            // the snapshot replaces the whole log, new entries continue right after it
+            self.set_raft_metadata(DiskStorageCore::FIRST_INDEX, index + 1)?;
+            self.set_raft_metadata(DiskStorageCore::LAST_INDEX, index)?;
+            for segment in self.segments.drain(..) {
+                LogSegment::remove_files(&self.dir, segment.first_index)?;
+            }
+            self.snapshot = snapshot;
+            Ok(())
+        }
+
+        // discards all entries before compact_index, segments only holding such entries are removed.
+        // The entries must be covered by the latest snapshot.
+        fn compact(&mut self, compact_index: u64) -> Result<(), Error> {
+            let first_index = self.first_index()?;
+            if compact_index <= first_index {
+                return Ok(());
+            }
+            let last_index = self.last_index()?;
+            if compact_index > last_index + 1 {
+                panic!(
+                    "compact not received raft logs: {}, last index: {}",
+                    compact_index, last_index
+                );
+            }
+            let snapshot_index = self.snapshot.get_metadata().index;
+            if compact_index > snapshot_index + 1 {
+                panic!(
+                    "compact beyond the latest snapshot: {}, snapshot index: {}",
+                    compact_index, snapshot_index
+                );
+            }
+            self.set_raft_metadata(DiskStorageCore::FIRST_INDEX, compact_index)?;
+            let num_compacted = self
+                .segments
+                .iter()
+                .take_while(|segment| segment.first_index + segment.num_entries <= compact_index)
+                .count();
+            for segment in self.segments.drain(..num_compacted) {
+                LogSegment::remove_files(&self.dir, segment.first_index)?;
+            }
+            Ok(())
+        }
+
+        fn set_conf_metadata(&self, meta: &mut SnapshotMetadata) {
+            meta.set_conf_state(self.conf_state.clone());
+            if let Some(cs) = self.pending_conf_state.as_ref() {
+                meta.set_pending_membership_change(cs.clone());
+                meta.pending_membership_change_index = self
+                    .pending_conf_state_start_index
+                    .expect("Pending conf state without start index");
+            }
+        }
+
+        fn persist_snapshot(&self, snapshot: &Snapshot) -> Result<(), Error> {
+            let ser_snapshot = snapshot
+                .write_to_bytes()
+                .expect("Protobuf failed to serialise Snapshot");
+            self.persist_file("snapshot", &ser_snapshot)
+        }
+
+        // the conf state is stored as SnapshotMetadata since it also holds any pending membership change
+        fn persist_conf_state(&self) -> Result<(), Error> {
+            let mut meta = SnapshotMetadata::default();
+            self.set_conf_metadata(&mut meta);
+            let ser_meta = meta
+                .write_to_bytes()
+                .expect("Protobuf failed to serialise conf state");
+            self.persist_file("conf_state", &ser_meta)
+        }
+
+        fn persist_file(&self, name: &str, bytes: &[u8]) -> Result<(), Error> {
+            // write to a temporary file first so a crash never leaves a partial file behind
+            let tmp_path = PathBuf::from(format!("{}/{}.tmp", self.dir, name));
+            let mut tmp_file = File::create(&tmp_path)?;
+            tmp_file.write_all(bytes)?;
+            tmp_file.sync_all()?;
+            rename(&tmp_path, PathBuf::from(format!("{}/{}", self.dir, name)))?;
+            Ok(())
+        }
+
+        fn read_persisted<M: PbMessage>(&self, name: &str) -> Option<M> {
+            let path = PathBuf::from(format!("{}/{}", self.dir, name));
+            match read(&path) {
+                Ok(bytes) => Some(
+                    parse_from_bytes::<M>(&bytes)
+                        .unwrap_or_else(|_| panic!("Protobuf failed to deserialise {}", name)),
+                ),
+                Err(e) if e.kind() == NotFound => None,
+                Err(e) => panic!("Failed to read {}: {:?}", name, e),
+            }
+        }
+
         fn clear_dir(&mut self) -> Result<(), IOError> {
             match remove_dir_all(&self.dir) {
                 Ok(_) => Ok(()),
@@ -493,7 +1146,9 @@ pub mod raft {
     impl Storage for DiskStorageCore {
         fn initial_state(&self) -> Result<RaftState, Error> {
             let hard_state = self.get_hard_state();
-            let rs = RaftState::new(hard_state, self.conf_state.clone());
+            let mut rs = RaftState::new(hard_state, self.conf_state.clone());
+            rs.pending_conf_state = self.pending_conf_state.clone();
+            rs.pending_conf_state_start_index = self.pending_conf_state_start_index;
             Ok(rs)
         }
 
@@ -515,12 +1170,9 @@ pub mod raft {
                     high
                 );
             }
-            let offset = first_index;
-            let lo = low - offset;
-            let hi = high - offset;
             let mut ents: Vec<Entry> = Vec::new();
-            for i in lo..hi {
-                ents.push(self.get_entry(i))
+            for i in low..high {
+                ents.push(self.get_entry(i)?)
             }
             let max_size = max_size.into();
             limit_size(&mut ents, max_size);
@@ -528,49 +1180,56 @@ pub mod raft {
         }
 
         fn term(&self, idx: u64) -> Result<u64, Error> {
-            if idx == self.snapshot_metadata.index {
-                return Ok(self.snapshot_metadata.term);
+            let snapshot_metadata = self.snapshot.get_metadata();
+            if idx == snapshot_metadata.index {
+                return Ok(snapshot_metadata.term);
             }
             let offset = self.first_index()?;
             if idx < offset {
                 return Err(Error::Store(StorageError::Compacted));
             }
-            let log_index = idx - offset;
-            if log_index >= self.num_entries {
+            let last_index = self.last_index()?;
+            if idx > last_index {
                 println!(
                     "{}",
                     format!(
-                        "log_index: {}, num_entries: {}, idx: {}, offset: {}",
-                        log_index, self.num_entries, idx, offset
+                        "idx: {}, last_index: {}, offset: {}",
+                        idx, last_index, offset
                     )
                 );
                 return Err(Error::Store(StorageError::Unavailable));
             }
-            Ok(self.get_entry(log_index).term)
+            Ok(self.get_entry(idx)?.term)
         }
 
         fn first_index(&self) -> Result<u64, Error> {
             match self.get_raft_metadata(DiskStorageCore::FIRST_INDEX) {
                 Some(index) => Ok(index),
-                None => Ok(self.snapshot_metadata.index + 1),
+                None => Ok(self.snapshot.get_metadata().index + 1),
             }
         }
 
         fn last_index(&self) -> Result<u64, Error> {
             match self.get_raft_metadata(DiskStorageCore::LAST_INDEX) {
                 Some(index) => Ok(index),
-                None => Ok(self.snapshot_metadata.index),
+                None => Ok(self.snapshot.get_metadata().index),
             }
         }
 
-        fn snapshot(&self, _request_index: u64) -> Result<Snapshot, Error> {
-            unimplemented!();
+        fn snapshot(&self, request_index: u64) -> Result<Snapshot, Error> {
+            if self.snapshot.get_metadata().index < request_index {
+                // no snapshot has been created that far yet, raft will retry later
+                return Err(Error::Store(StorageError::SnapshotTemporarilyUnavailable));
+            }
+            Ok(self.snapshot.clone())
         }
     }
 
     #[cfg(test)]
     mod test {
+        use super::tikv_raft::storage::MemStorage as ModelStorage;
         use super::*;
+        use rand::{rngs::SmallRng, Rng, SeedableRng};
         use std::fs::remove_dir_all;
 
         fn new_entry(index: u64, term: u64) -> Entry {
@@ -584,6 +1243,254 @@ pub mod raft {
             m.compute_size() as u32
         }
 
+        fn random_entry(rng: &mut SmallRng, index: u64, term: u64) -> Entry {
+            let mut e = new_entry(index, term);
+            let len = rng.gen_range(0, 256);
+            e.set_data((0..len).map(|_| rng.gen()).collect());
+            e
+        }
+
+        fn random_conf_state(rng: &mut SmallRng) -> ConfState {
+            let mut voters = vec![];
+            let mut learners = vec![];
+            for pid in 1..=5 {
+                match rng.gen_range(0, 3) {
+                    0 => voters.push(pid),
+                    1 => learners.push(pid),
+                    _ => {}
+                }
+            }
+            if voters.is_empty() {
+                voters.push(1);
+            }
+            ConfState::from((voters, learners))
+        }
+
+        fn check_reads<S: RaftStorage>(
+            model: &ModelStorage,
+            snapshot: &Snapshot,
+            storage: &S,
+            rng: &mut SmallRng,
+            step: &str,
+        ) {
+            let first_index = model.first_index().unwrap();
+            let last_index = model.last_index().unwrap();
+            assert_eq!(
+                storage.first_index(),
+                Ok(first_index),
+                "{}: first_index",
+                step
+            );
+            assert_eq!(storage.last_index(), Ok(last_index), "{}: last_index", step);
+            let model_state = model.initial_state().unwrap();
+            let state = storage.initial_state().unwrap();
+            assert_eq!(
+                state.hard_state, model_state.hard_state,
+                "{}: hard_state",
+                step
+            );
+            assert_eq!(
+                state.conf_state, model_state.conf_state,
+                "{}: conf_state",
+                step
+            );
+            assert_eq!(
+                state.pending_conf_state, model_state.pending_conf_state,
+                "{}: pending_conf_state",
+                step
+            );
+            assert_eq!(
+                state.pending_conf_state_start_index, model_state.pending_conf_state_start_index,
+                "{}: pending_conf_state_start_index",
+                step
+            );
+            assert_eq!(
+                storage.snapshot(0),
+                Ok(snapshot.clone()),
+                "{}: snapshot",
+                step
+            );
+            for idx in first_index - 1..=last_index + 1 {
+                // the model forgets the term of a snapshot it did not apply once it is compacted
+                let term = if idx == snapshot.get_metadata().index {
+                    Ok(snapshot.get_metadata().term)
+                } else {
+                    model.term(idx)
+                };
+                assert_eq!(storage.term(idx), term, "{}: term({})", step, idx);
+            }
+            if last_index < first_index {
+                return;
+            }
+            assert_eq!(
+                storage.entries(first_index, last_index + 1, None),
+                model.entries(first_index, last_index + 1, None),
+                "{}: all entries",
+                step
+            );
+            let low = rng.gen_range(first_index, last_index + 1);
+            let high = rng.gen_range(low + 1, last_index + 2);
+            let max_size: Option<u64> = if rng.gen_bool(0.5) {
+                Some(rng.gen_range(0, 2048))
+            } else {
+                None
+            };
+            assert_eq!(
+                storage.entries(low, high, max_size),
+                model.entries(low, high, max_size),
+                "{}: entries({}, {}, {:?})",
+                step,
+                low,
+                high,
+                max_size
+            );
+        }
+
+        // Applies the same random appends, overwrites, metadata updates, snapshots and compactions
+        // to S and to tikv's MemStorage, which serves as the reference, and checks that all reads
+        // agree after each. The model does not keep snapshots, the latest one is tracked alongside.
+        fn check_conformance<S: RaftStorage>(dir: &str, seed: u64, num_ops: usize) {
+            let mut rng = SmallRng::seed_from_u64(seed);
+            let conf_state = (vec![1, 2, 3], vec![]);
+            let model = ModelStorage::new_with_conf_state(conf_state.clone());
+            let mut snapshot = model.snapshot(0).expect("Failed to get initial snapshot");
+            let mut storage = S::new_with_conf_state(Some(dir), conf_state);
+            let mut term = 1;
+            for op in 0..num_ops {
+                let first_index = model.first_index().unwrap();
+                let last_index = model.last_index().unwrap();
+                let snapshot_index = snapshot.get_metadata().index;
+                match rng.gen_range(0, 12) {
+                    0..=5 => {
+                        let start = if last_index > snapshot_index && rng.gen_bool(0.3) {
+                            // a new leader overwrites a suffix of the log with a higher term,
+                            // never the snapshotted entries
+                            term += 1;
+                            rng.gen_range(snapshot_index + 1, last_index + 1)
+                        } else {
+                            last_index + 1
+                        };
+                        let num_entries = rng.gen_range(1, 8);
+                        let ents: Vec<Entry> = (start..start + num_entries)
+                            .map(|index| random_entry(&mut rng, index, term))
+                            .collect();
+                        model.wl().append(&ents).expect("Failed to append logs");
+                        storage.append_log(&ents).expect("Failed to append logs");
+                    }
+                    6 => {
+                        let cs = random_conf_state(&mut rng);
+                        let pending = if rng.gen_bool(0.3) {
+                            // started by the next entry, index 0 would read as no pending change
+                            Some((random_conf_state(&mut rng), last_index + 1))
+                        } else {
+                            None
+                        };
+                        model.wl().set_conf_state(cs.clone(), pending.clone());
+                        storage.set_conf_state(cs, pending);
+                    }
+                    7 => {
+                        let commit = rng.gen_range(0, last_index + 1);
+                        if rng.gen_bool(0.5) {
+                            model.wl().mut_hard_state().commit = commit;
+                            model.wl().mut_hard_state().term = term;
+                            storage
+                                .set_hard_state(commit, term)
+                                .expect("Failed to set hard state");
+                        } else {
+                            let mut hs = HardState::default();
+                            hs.term = term;
+                            hs.vote = rng.gen_range(0, 6);
+                            hs.commit = commit;
+                            *model.wl().mut_hard_state() = hs.clone();
+                            storage
+                                .save_hard_state(hs)
+                                .expect("Failed to save hard state");
+                        }
+                    }
+                    8 => {
+                        // only storages that persist anything can be reopened
+                        if let Some(recovered) = S::recover(Some(dir)) {
+                            storage = recovered;
+                        }
+                    }
+                    9 if last_index > snapshot_index => {
+                        let index = rng.gen_range(snapshot_index + 1, last_index + 1);
+                        let data: Vec<u8> = (0..rng.gen_range(0, 64)).map(|_| rng.gen()).collect();
+                        let state = model.initial_state().unwrap();
+                        let mut expected = Snapshot::default();
+                        expected.set_data(data.clone());
+                        let meta = expected.mut_metadata();
+                        meta.index = index;
+                        meta.term = model.term(index).unwrap();
+                        meta.set_conf_state(state.conf_state);
+                        if let Some(cs) = state.pending_conf_state {
+                            meta.set_pending_membership_change(cs);
+                            meta.pending_membership_change_index =
+                                state.pending_conf_state_start_index.unwrap();
+                        }
+                        assert_eq!(
+                            storage.create_snapshot(index, data),
+                            Ok(expected.clone()),
+                            "seed {} op {}: create_snapshot({})",
+                            seed,
+                            op,
+                            index
+                        );
+                        snapshot = expected;
+                    }
+                    10 if first_index <= snapshot_index => {
+                        let compact_index = rng.gen_range(first_index, snapshot_index + 2);
+                        model
+                            .wl()
+                            .compact(compact_index)
+                            .expect("Failed to compact");
+                        storage.compact(compact_index).expect("Failed to compact");
+                    }
+                    11 => {
+                        // a lagging follower gets the leader's snapshot, which replaces its log
+                        let mut received = Snapshot::default();
+                        received.set_data((0..rng.gen_range(0, 64)).map(|_| rng.gen()).collect());
+                        let meta = received.mut_metadata();
+                        meta.index = last_index + rng.gen_range(1, 8);
+                        meta.term = term;
+                        meta.set_conf_state(random_conf_state(&mut rng));
+                        let state = model.initial_state().unwrap();
+                        if state.pending_conf_state.is_some() || rng.gen_bool(0.3) {
+                            // a pending membership change is carried along until it is finalized
+                            meta.set_pending_membership_change(random_conf_state(&mut rng));
+                            meta.pending_membership_change_index = meta.index;
+                        }
+                        model
+                            .wl()
+                            .apply_snapshot(received.clone())
+                            .expect("Failed to apply snapshot");
+                        storage
+                            .apply_snapshot(received.clone())
+                            .expect("Failed to apply snapshot");
+                        snapshot = received;
+                    }
+                    _ => {}
+                }
+                let step = format!("seed {} op {}", seed, op);
+                check_reads(&model, &snapshot, &storage, &mut rng, &step);
+            }
+            storage.clear().expect("Failed to clear storage");
+        }
+
+        #[test]
+        fn memstorage_conformance_test() {
+            for seed in 0..5 {
+                check_conformance::<MemStorage>("memstorage_conformance_test", seed, 200);
+            }
+        }
+
+        #[test]
+        fn diskstorage_conformance_test() {
+            for seed in 0..5 {
+                check_conformance::<DiskStorage>("diskstorage_conformance_test", seed, 200);
+            }
+        }
+
         #[test]
         fn diskstorage_term_test() {
             let ents = vec![new_entry(2, 2), new_entry(3, 3)];
@@ -719,5 +1626,528 @@ pub mod raft {
             assert_eq!(storage.last_index(), Ok(5));
             remove_dir_all("metadata_test").expect("Failed to remove test storage files");
         }
+
+        #[test]
+        fn diskstorage_snapshot_test() {
+            let ents = vec![
+                new_entry(2, 2),
+                new_entry(3, 3),
+                new_entry(4, 4),
+                new_entry(5, 5),
+            ];
+            let mut storage =
+                DiskStorage::new_with_conf_state("snapshot_test", (vec![1, 2, 3], vec![]));
+            storage.append_log(&ents).expect("Failed to append logs");
+            let snapshot = storage
+                .create_snapshot(4, vec![1, 2, 3])
+                .expect("Failed to create snapshot");
+            assert_eq!(snapshot.get_metadata().index, 4);
+            assert_eq!(snapshot.get_metadata().term, 4);
+            assert_eq!(
+                snapshot.get_metadata().get_conf_state(),
+                &ConfState::from((vec![1, 2, 3], vec![]))
+            );
+            assert_eq!(storage.snapshot(4), Ok(snapshot.clone()));
+            assert_eq!(
+                storage.snapshot(5),
+                Err(Error::Store(StorageError::SnapshotTemporarilyUnavailable))
+            );
+            assert_eq!(
+                storage.create_snapshot(3, vec![]),
+                Err(Error::Store(StorageError::SnapshotOutOfDate))
+            );
+
+            let mut follower =
+                DiskStorage::new_with_conf_state("snapshot_follower_test", (vec![1, 2, 3], vec![]));
+            follower
+                .apply_snapshot(snapshot.clone())
+                .expect("Failed to apply snapshot");
+            assert_eq!(follower.first_index(), Ok(5));
+            assert_eq!(follower.last_index(), Ok(4));
+            assert_eq!(follower.term(4), Ok(4));
+            assert_eq!(follower.snapshot(4), Ok(snapshot));
+            assert_eq!(follower.initial_state().unwrap().hard_state.commit, 4);
+            follower
+                .append_log(&[new_entry(5, 5)])
+                .expect("Failed to append logs");
+            assert_eq!(follower.entries(5, 6, None), Ok(vec![new_entry(5, 5)]));
+            remove_dir_all("snapshot_test").expect("Failed to remove test storage files");
+            remove_dir_all("snapshot_follower_test").expect("Failed to remove test storage files");
+        }
+
+        #[test]
+        fn memstorage_snapshot_test() {
+            let ents = vec![
+                new_entry(2, 2),
+                new_entry(3, 3),
+                new_entry(4, 4),
+                new_entry(5, 5),
+            ];
+            let mut storage = MemStorage::new_with_conf_state(None, (vec![1, 2, 3], vec![]));
+            storage.append_log(&ents).expect("Failed to append logs");
+            let snapshot = storage
+                .create_snapshot(4, vec![1, 2, 3])
+                .expect("Failed to create snapshot");
+            assert_eq!(snapshot.get_metadata().index, 4);
+            assert_eq!(snapshot.get_metadata().term, 4);
+            // the snapshot sent to followers carries the data, also once the log is compacted
+            storage.compact(5).expect("Failed to compact");
+            assert_eq!(storage.term(4), Ok(4));
+            assert_eq!(storage.snapshot(0), Ok(snapshot.clone()));
+            assert_eq!(
+                storage.snapshot(5),
+                Err(Error::Store(StorageError::SnapshotTemporarilyUnavailable))
+            );
+            assert_eq!(
+                storage.create_snapshot(3, vec![]),
+                Err(Error::Store(StorageError::SnapshotOutOfDate))
+            );
+
+            let mut follower = MemStorage::new_with_conf_state(None, (vec![1, 2, 3], vec![]));
+            follower
+                .apply_snapshot(snapshot.clone())
+                .expect("Failed to apply snapshot");
+            assert_eq!(follower.first_index(), Ok(5));
+            assert_eq!(follower.last_index(), Ok(4));
+            assert_eq!(follower.snapshot(0), Ok(snapshot));
+        }
+
+        #[test]
+        fn diskstorage_compact_test() {
+            let ents = vec![
+                new_entry(2, 2),
+                new_entry(3, 3),
+                new_entry(4, 4),
+                new_entry(5, 5),
+            ];
+            let mut storage =
+                DiskStorage::new_with_conf_state("compact_test", (vec![1, 2, 3], vec![]));
+            storage.append_log(&ents).expect("Failed to append logs");
+            storage
+                .create_snapshot(3, vec![])
+                .expect("Failed to create snapshot");
+            storage.compact(4).expect("Failed to compact");
+            assert_eq!(storage.first_index(), Ok(4));
+            assert_eq!(storage.last_index(), Ok(5));
+            assert_eq!(storage.term(3), Ok(3));
+            assert_eq!(storage.term(2), Err(Error::Store(StorageError::Compacted)));
+            assert_eq!(
+                storage.entries(4, 6, None),
+                Ok(vec![new_entry(4, 4), new_entry(5, 5)])
+            );
+            // appending and overwriting continues after the rewritten entries
+            storage
+                .append_log(&[new_entry(5, 6), new_entry(6, 6)])
+                .expect("Failed to append logs");
+            assert_eq!(
+                storage.entries(4, 7, None),
+                Ok(vec![new_entry(4, 4), new_entry(5, 6), new_entry(6, 6)])
+            );
+            storage
+                .create_snapshot(6, vec![])
+                .expect("Failed to create snapshot");
+            storage.compact(7).expect("Failed to compact");
+            assert_eq!(storage.first_index(), Ok(7));
+            assert_eq!(storage.last_index(), Ok(6));
+            storage
+                .append_log(&[new_entry(7, 7)])
+                .expect("Failed to append logs");
+            assert_eq!(storage.entries(7, 8, None), Ok(vec![new_entry(7, 7)]));
+            remove_dir_all("compact_test").expect("Failed to remove test storage files");
+        }
+
+        #[test]
+        fn diskstorage_recover_test() {
+            let ents = vec![
+                new_entry(2, 2),
+                new_entry(3, 3),
+                new_entry(4, 4),
+                new_entry(5, 5),
+            ];
+            let conf_state = ConfState::from((vec![1, 2, 3], vec![4]));
+            {
+                let mut storage =
+                    DiskStorage::new_with_conf_state("recover_test", (vec![1, 2, 3], vec![]));
+                storage.append_log(&ents).expect("Failed to append logs");
+                storage
+                    .append_log(&[new_entry(4, 5), new_entry(5, 5)])
+                    .expect("Failed to append logs");
+                storage
+                    .set_hard_state(3, 5)
+                    .expect("Failed to set hard state");
+                storage.set_conf_state(conf_state.clone(), None);
+                storage
+                    .create_snapshot(3, vec![1, 2])
+                    .expect("Failed to create snapshot");
+            }
+            let mut recovered = DiskStorage::open("recover_test");
+            assert_eq!(recovered.first_index(), Ok(2));
+            assert_eq!(recovered.last_index(), Ok(5));
+            assert_eq!(
+                recovered.entries(2, 6, None),
+                Ok(vec![
+                    new_entry(2, 2),
+                    new_entry(3, 3),
+                    new_entry(4, 5),
+                    new_entry(5, 5)
+                ])
+            );
+            let state = recovered
+                .initial_state()
+                .expect("Failed to get initial state");
+            assert_eq!(state.hard_state.commit, 3);
+            assert_eq!(state.hard_state.term, 5);
+            assert_eq!(state.conf_state, conf_state);
+            assert_eq!(
+                recovered.snapshot(0).unwrap().get_data().to_vec(),
+                vec![1u8, 2]
+            );
+            // appending continues after the recovered entries
+            recovered
+                .append_log(&[new_entry(6, 6)])
+                .expect("Failed to append logs");
+            assert_eq!(
+                recovered.entries(5, 7, None),
+                Ok(vec![new_entry(5, 5), new_entry(6, 6)])
+            );
+            remove_dir_all("recover_test").expect("Failed to remove test storage files");
+        }
+
+        #[test]
+        fn diskstorage_segments_test() {
+            // entries taking up most of a segment, the last one does not fit into a segment at all
+            let ents: Vec<Entry> = [5_000_000, 5_000_000, 3_000_000, 10_000_000]
+                .iter()
+                .enumerate()
+                .map(|(i, size)| {
+                    let mut e = new_entry(i as u64 + 2, 2);
+                    e.set_data(vec![i as u8; *size]);
+                    e
+                })
+                .collect();
+            let num_segments = || {
+                read_dir("segments_test")
+                    .expect("Failed to read test storage dir")
+                    .filter(|e| {
+                        let name = e.as_ref().unwrap().file_name();
+                        name.to_str().unwrap().ends_with(".log")
+                    })
+                    .count()
+            };
+            {
+                let mut storage =
+                    DiskStorage::new_with_conf_state("segments_test", (vec![1, 2, 3], vec![]));
+                storage.append_log(&ents).expect("Failed to append logs");
+                assert_eq!(num_segments(), 3);
+                assert_eq!(storage.entries(2, 6, None), Ok(ents.clone()));
+                storage.compact(4).expect("Failed to compact");
+                assert_eq!(num_segments(), 2);
+            }
+            let mut recovered = DiskStorage::open("segments_test");
+            assert_eq!(recovered.first_index(), Ok(4));
+            assert_eq!(recovered.last_index(), Ok(5));
+            assert_eq!(recovered.entries(4, 6, None), Ok(ents[2..].to_vec()));
+            // overwriting removes the segments after the overwritten entry
+            recovered
+                .append_log(&[new_entry(4, 3)])
+                .expect("Failed to append logs");
+            assert_eq!(num_segments(), 1);
+            assert_eq!(recovered.last_index(), Ok(4));
+            assert_eq!(recovered.entries(4, 5, None), Ok(vec![new_entry(4, 3)]));
+            remove_dir_all("segments_test").expect("Failed to remove test storage files");
+        }
+
+        #[test]
+        fn diskstorage_durability_test() {
+            for mode in &["none", "flush", "fsync-batch", "fsync-entry"] {
+                let durability = mode
+                    .parse::<Durability>()
+                    .expect("Failed to parse durability");
+                {
+                    let mut storage = DiskStorage::new_with_conf_state(
+                        "durability_test",
+                        (vec![1, 2, 3], vec![]),
+                    );
+                    storage.set_durability(durability);
+                    storage
+                        .append_log(&[new_entry(2, 2), new_entry(3, 2)])
+                        .expect("Failed to append logs");
+                    storage
+                        .append_log(&[new_entry(3, 3), new_entry(4, 3)])
+                        .expect("Failed to append logs");
+                    storage
+                        .set_hard_state(4, 3)
+                        .expect("Failed to set hard state");
+                }
+                let recovered = DiskStorage::open("durability_test");
+                assert_eq!(
+                    recovered.entries(2, 5, None),
+                    Ok(vec![new_entry(2, 2), new_entry(3, 3), new_entry(4, 3)])
+                );
+                assert_eq!(recovered.initial_state().unwrap().hard_state.commit, 4);
+                remove_dir_all("durability_test").expect("Failed to remove test storage files");
+            }
+            assert!("fsync".parse::<Durability>().is_err());
+        }
+
+        #[test]
+        fn diskstorage_vote_test() {
+            {
+                let mut storage =
+                    DiskStorage::new_with_conf_state("vote_test", (vec![1, 2, 3], vec![]));
+                let mut hs = HardState::default();
+                hs.term = 3;
+                hs.vote = 2;
+                hs.commit = 1;
+                storage
+                    .save_hard_state(hs)
+                    .expect("Failed to save hard state");
+            }
+            let recovered = DiskStorage::open("vote_test");
+            let config = Config {
+                id: 1,
+                election_tick: 10,
+                heartbeat_tick: 1,
+                ..Default::default()
+            };
+            let mut node = RawNode::new(&config, recovered).expect("Failed to create RawNode");
+            assert_eq!(node.raft.term, 3);
+            assert_eq!(node.raft.vote, 2);
+            // a restarted node must not grant a second vote in the same term
+            let mut request = Message::default();
+            request.set_msg_type(MessageType::MsgRequestVote);
+            request.from = 3;
+            request.to = 1;
+            request.term = 3;
+            request.log_term = 1;
+            request.index = 1;
+            node.step(request).expect("Failed to step vote request");
+            let ready = node.ready();
+            let response = ready
+                .messages
+                .iter()
+                .find(|m| m.get_msg_type() == MessageType::MsgRequestVoteResponse)
+                .expect("No vote response");
+            assert!(response.reject);
+            remove_dir_all("vote_test").expect("Failed to remove test storage files");
+        }
+
+        #[test]
+        fn diskstorage_corruption_test() {
+            let ents = vec![
+                new_entry(2, 2),
+                new_entry(3, 3),
+                new_entry(4, 4),
+                new_entry(5, 5),
+            ];
+            {
+                let mut storage =
+                    DiskStorage::new_with_conf_state("corruption_test", (vec![1, 2, 3], vec![]));
+                storage.append_log(&ents).expect("Failed to append logs");
+                storage
+                    .set_hard_state(5, 5)
+                    .expect("Failed to set hard state");
+                // flip the first byte of the serialised entry with index 4
+                {
+                    let mut core = storage.wl();
+                    let segment = &mut core.segments[0];
+                    let pos = segment.get_offset(2) + LogSegment::RECORD_HEADER;
+                    segment.log[pos] ^= 0xff;
+                }
+                assert_eq!(storage.entries(2, 4, None), Ok(ents[..2].to_vec()));
+                match storage.entries(2, 6, None) {
+                    Err(Error::Store(StorageError::Other(e))) => assert_eq!(
+                        e.downcast_ref::<LogCorruption>(),
+                        Some(&LogCorruption::ChecksumMismatch(4))
+                    ),
+                    res => panic!("expected a corrupted entry, got {:?}", res),
+                }
+            }
+            // recovery keeps the entries before the corrupted one
+            let mut recovered = DiskStorage::open("corruption_test");
+            assert_eq!(recovered.first_index(), Ok(2));
+            assert_eq!(recovered.last_index(), Ok(3));
+            assert_eq!(recovered.entries(2, 4, None), Ok(ents[..2].to_vec()));
+            assert_eq!(recovered.initial_state().unwrap().hard_state.commit, 3);
+            recovered
+                .append_log(&ents[2..])
+                .expect("Failed to append logs");
+            assert_eq!(recovered.entries(2, 6, None), Ok(ents));
+            remove_dir_all("corruption_test").expect("Failed to remove test storage files");
+        }
+
+        #[test]
+        fn diskstorage_sync_test() {
+            let mut storage =
+                DiskStorage::new_with_conf_state("sync_test", (vec![1, 2, 3], vec![]));
+            storage
+                .append_log(&[new_entry(2, 2), new_entry(3, 2)])
+                .expect("Failed to append logs");
+            // the segment's log and offset file and the two metadata files
+            assert_eq!(storage.wl().take_unsynced_files().unwrap().len(), 4);
+            assert_eq!(storage.wl().take_unsynced_files().unwrap().len(), 2);
+            storage
+                .append_log(&[new_entry(4, 2)])
+                .expect("Failed to append logs");
+            storage.sync().expect("Failed to sync");
+            assert_eq!(storage.wl().take_unsynced_files().unwrap().len(), 2);
+            remove_dir_all("sync_test").expect("Failed to remove test storage files");
+        }
+    }
+}
+
+pub mod paxos {
+    use leaderpaxos::{
+        leader_election::Round,
+        storage::{Entry, PaxosState, Sequence, SequenceTraits, StateTraits},
+    };
+
+    #[derive(Debug)]
+    pub struct MemorySequence<R>
+    where
+        R: Round,
+    {
+        sequence: Vec<Entry<R>>,
+    }
+
+    impl<R> Sequence<R> for MemorySequence<R>
+    where
+        R: Round,
+    {
+        fn new() -> Self {
+            MemorySequence { sequence: vec![] }
+        }
+
+        fn new_with_sequence(seq: Vec<Entry<R>>) -> Self {
+            MemorySequence { sequence: seq }
+        }
+
+        fn append_entry(&mut self, entry: Entry<R>) {
+            self.sequence.push(entry);
+        }
+
+        fn append_sequence(&mut self, seq: &mut Vec<Entry<R>>) {
+            self.sequence.append(seq);
+        }
+
+        fn append_on_prefix(&mut self, from_idx: u64, seq: &mut Vec<Entry<R>>) {
+            self.sequence.truncate(from_idx as usize);
+            self.sequence.append(seq);
+        }
+
+        fn get_entries(&self, from: u64, to: u64) -> &[Entry<R>] {
+            match self.sequence.get(from as usize..to as usize) {
+                Some(entries) => entries,
+                None => panic!(
+                    "get_entries out of bounds. From: {}, To: {}, len: {}",
+                    from,
+                    to,
+                    self.sequence.len()
+                ),
+            }
+        }
+
+        fn get_ser_entries(&self, _from: u64, _to: u64) -> Option<Vec<u8>> {
+            None // entries are serialised by the PaxosMsgWrapper
+        }
+
+        fn get_suffix(&self, from: u64) -> Vec<Entry<R>> {
+            match self.sequence.get(from as usize..) {
+                Some(s) => s.to_vec(),
+                None => vec![],
+            }
+        }
+
+        fn get_ser_suffix(&self, _from: u64) -> Option<Vec<u8>> {
+            None
+        }
+
+        fn get_sequence(&self) -> Vec<Entry<R>> {
+            self.sequence.clone()
+        }
+
+        fn get_sequence_len(&self) -> u64 {
+            self.sequence.len() as u64
+        }
+
+        fn stopped(&self) -> bool {
+            match self.sequence.last() {
+                Some(entry) => entry.is_stopsign(),
+                None => false,
+            }
+        }
+    }
+
+    impl<R> SequenceTraits<R> for MemorySequence<R> where R: Round {}
+
+    #[derive(Debug)]
+    pub struct MemoryState<R>
+    where
+        R: Round,
+    {
+        n_prom: R,
+        acc_round: R,
+        ld: u64,
+    }
+
+    impl<R> PaxosState<R> for MemoryState<R>
+    where
+        R: Round,
+    {
+        fn new() -> Self {
+            MemoryState {
+                n_prom: R::default(),
+                acc_round: R::default(),
+                ld: 0,
+            }
+        }
+
+        fn set_promise(&mut self, nprom: R) {
+            self.n_prom = nprom;
+        }
+
+        fn set_decided_len(&mut self, ld: u64) {
+            self.ld = ld;
+        }
+
+        fn set_accepted_round(&mut self, na: R) {
+            self.acc_round = na;
+        }
+
+        fn get_accepted_round(&self) -> R {
+            self.acc_round.clone()
+        }
+
+        fn get_decided_len(&self) -> u64 {
+            self.ld
+        }
+
+        fn get_promise(&self) -> R {
+            self.n_prom.clone()
+        }
+    }
+
+    impl<R> StateTraits<R> for MemoryState<R> where R: Round {}
+
+    #[cfg(test)]
+    mod test {
+        use super::*;
+        use crate::atomic_broadcast::messages::paxos::Ballot;
+        use leaderpaxos::storage::StopSign;
+
+        #[test]
+        fn append_on_prefix_truncates_test() {
+            let mut seq: MemorySequence<Ballot> = MemorySequence::new();
+            let mut entries = (0..10u8).map(|i| Entry::Normal(vec![i])).collect();
+            seq.append_sequence(&mut entries);
+            let mut suffix = vec![Entry::Normal(vec![42])];
+            seq.append_on_prefix(5, &mut suffix);
+            assert_eq!(seq.get_sequence_len(), 6);
+            assert!(matches!(&seq.get_entries(5, 6)[0], Entry::Normal(d) if d == &vec![42]));
+            assert!(!seq.stopped());
+            seq.append_entry(Entry::StopSign(StopSign::with(2, vec![1, 2, 3], None)));
+            assert!(seq.stopped());
+            assert_eq!(seq.get_suffix(6).len(), 1);
+        }
     }
-}
\ No newline at end of file
+}
@@ -5,20 +5,12 @@ mod atomic_broadcast;
 extern crate raft as tikv_raft;
 use kompact::prelude::{KompactSystem, ActorPath, Recipient, KompactConfig, BufferConfig, Ask, promise, FutureCollection};
 
-use atomic_broadcast::{raft::{RaftCompMsg, RaftComp, ReconfigurationPolicy as RaftReconfigurationPolicy}, atomic_broadcast::{REGISTER_TIMEOUT, RAFT_PATH, CONFIG_PATH, SequenceResp}};
-use tikv_raft::storage::MemStorage;
+use atomic_broadcast::{raft::{RaftComp, ReconfigurationPolicy as RaftReconfigurationPolicy}, atomic_broadcast::{REGISTER_TIMEOUT, RAFT_PATH, CONFIG_PATH, SequenceResp, ExperimentConfig}};
 use kompact::prelude::ActorRefFactory;
-type Storage = MemStorage;
 
 #[derive(Debug)]
 pub struct GetSequence(Ask<(), SequenceResp>);
 
-impl Into<RaftCompMsg> for GetSequence {
-    fn into(self) -> RaftCompMsg {
-        RaftCompMsg::GetSequence(self.0)
-    }
-}
-
 fn raft_normal_test() {
     println!("running test");
     let num_nodes = 3;
@@ -26,6 +18,7 @@ fn raft_normal_test() {
     let concurrent_proposals = 200;
     let reconfiguration = "off";
     let reconfig_policy = "none";
+    let config = ExperimentConfig::load_from_file(CONFIG_PATH);
     run_experiment(
         "raft",
         num_nodes,
@@ -33,6 +26,7 @@ fn raft_normal_test() {
         concurrent_proposals,
         reconfiguration,
         reconfig_policy,
+        config,
     );
 }
 fn main() {